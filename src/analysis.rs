@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    data::{ItemStats, Project, Question},
+    scoring::StudentScore,
+};
+
+/// Share of the best and worst students compared for the discrimination index.
+const GROUP_SHARE: f64 = 0.27;
+
+fn share(points: f64, max_points: f64) -> f64 {
+    if max_points > 0.0 {
+        points / max_points
+    } else {
+        0.0
+    }
+}
+
+fn average(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Runs an item analysis over scored responses. Returns the statistics of every question that
+/// was scored, by its index in the question bank.
+pub fn analyze(project: &Project, scores: &[StudentScore]) -> BTreeMap<usize, ItemStats> {
+    let mut ranked: Vec<&StudentScore> = scores.iter().collect();
+    ranked.sort_by(|a, b| {
+        let a = share(a.points, a.max_points);
+        let b = share(b.points, b.max_points);
+        a.total_cmp(&b)
+    });
+    let group = ((ranked.len() as f64 * GROUP_SHARE).round() as usize).max(1);
+    let lower = &ranked[..group.min(ranked.len())];
+    let upper = &ranked[ranked.len().saturating_sub(group)..];
+
+    // The share of points a student got on the question with the given bank index
+    let result = |student: &StudentScore, index: usize| {
+        student
+            .questions
+            .iter()
+            .find(|q| q.index == index)
+            .map(|q| share(q.points, q.max_points))
+    };
+
+    let mut stats: BTreeMap<usize, ItemStats> = BTreeMap::new();
+    for score in scores.iter().flat_map(|s| s.questions.iter()) {
+        let entry = stats.entry(score.index).or_default();
+        entry.responses += 1;
+
+        if let Some(Question::Selection(q)) = project.questions.get(score.index) {
+            entry.distractors.resize(q.incorrect.len(), 0);
+            for source in score.picks.iter() {
+                if let Some(distractor) = source.checked_sub(q.correct.len()) {
+                    entry.distractors[distractor] += 1;
+                }
+            }
+        }
+    }
+
+    for (index, entry) in stats.iter_mut() {
+        let index = *index;
+        entry.difficulty = average(scores.iter().filter_map(|s| result(s, index))).unwrap_or(0.0);
+
+        let upper = average(upper.iter().filter_map(|s| result(s, index)));
+        let lower = average(lower.iter().filter_map(|s| result(s, index)));
+        entry.discrimination = match (upper, lower) {
+            (Some(upper), Some(lower)) => upper - lower,
+            _ => 0.0,
+        };
+    }
+
+    stats
+}
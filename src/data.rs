@@ -1,23 +1,312 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
+use anyhow::Context;
 use egui_notify::Toasts;
 use serde::{Deserialize, Serialize};
 
-use crate::settings::Settings;
+use crate::{history::History, markup, preview, settings::Settings};
 
 const fn default_points() -> u8 {
     1
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+}
+
+/// How the answers chosen for a selection question are turned into points.
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Copy)]
+#[serde(tag = "type")]
+pub enum ScoringPolicy {
+    /// The points are given only for choosing exactly the correct answers.
+    #[default]
+    AllOrNothing,
+    /// Every correct answer chosen is worth an equal share of the points, incorrect ones are
+    /// ignored.
+    Proportional,
+    /// Like `Proportional`, but every incorrect answer chosen takes `penalty` points away.
+    Penalty { penalty: f64, floor_at_zero: bool },
+}
+impl ScoringPolicy {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ScoringPolicy::AllOrNothing => "All or nothing",
+            ScoringPolicy::Proportional => "Proportional",
+            ScoringPolicy::Penalty { .. } => "Penalty",
+        }
+    }
+    pub fn is_all_or_nothing(&self) -> bool {
+        *self == ScoringPolicy::AllOrNothing
+    }
+
+    /// Points for choosing `right` of the `correct` answers and `wrong` incorrect ones.
+    pub fn score(&self, points: f64, correct: usize, right: usize, wrong: usize) -> f64 {
+        let share = match correct {
+            0 => (wrong == 0) as u8 as f64,
+            _ => right as f64 / correct as f64,
+        };
+        match *self {
+            ScoringPolicy::AllOrNothing if right == correct && wrong == 0 => points,
+            ScoringPolicy::AllOrNothing => 0.0,
+            ScoringPolicy::Proportional => points * share,
+            ScoringPolicy::Penalty {
+                penalty,
+                floor_at_zero,
+            } => {
+                let score = points * share - penalty * wrong as f64;
+                if floor_at_zero {
+                    score.max(0.0)
+                } else {
+                    score
+                }
+            }
+        }
+    }
+}
+
+/// The kind of a question, for filtering the question bank.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum QuestionType {
+    Selection,
+    Input,
+    TrueFalse,
+    Matching,
+    Cloze,
+    Ordering,
+    Numeric,
+}
+impl QuestionType {
+    pub const ALL: [QuestionType; 7] = [
+        QuestionType::Selection,
+        QuestionType::Input,
+        QuestionType::TrueFalse,
+        QuestionType::Matching,
+        QuestionType::Cloze,
+        QuestionType::Ordering,
+        QuestionType::Numeric,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            QuestionType::Selection => "Selection",
+            QuestionType::Input => "Input",
+            QuestionType::TrueFalse => "True/False",
+            QuestionType::Matching => "Matching",
+            QuestionType::Cloze => "Cloze",
+            QuestionType::Ordering => "Ordering",
+            QuestionType::Numeric => "Numeric",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ImageAlignment {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+impl ImageAlignment {
+    pub const ALL: [ImageAlignment; 3] = [
+        ImageAlignment::Left,
+        ImageAlignment::Center,
+        ImageAlignment::Right,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ImageAlignment::Left => "Left",
+            ImageAlignment::Center => "Center",
+            ImageAlignment::Right => "Right",
+        }
+    }
+}
+
+/// A PNG or JPEG file printed with a question or an answer.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct QuestionImage {
+    pub path: String,
+    /// Width on the paper in millimeters, the image keeps its own size when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    #[serde(default)]
+    pub alignment: ImageAlignment,
+}
+impl Default for QuestionImage {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            width: Some(60.0),
+            alignment: ImageAlignment::Center,
+        }
+    }
+}
+
+/// An answer of a selection question. Answers without an image are stored as plain strings.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(from = "AnswerRepr", into = "AnswerRepr")]
+pub struct Answer {
+    pub text: String,
+    pub image: Option<QuestionImage>,
+}
+impl From<&str> for Answer {
+    fn from(text: &str) -> Self {
+        Self {
+            text: text.into(),
+            image: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum AnswerRepr {
+    Text(String),
+    WithImage {
+        text: String,
+        image: Option<QuestionImage>,
+    },
+}
+impl From<AnswerRepr> for Answer {
+    fn from(repr: AnswerRepr) -> Self {
+        match repr {
+            AnswerRepr::Text(text) => Self { text, image: None },
+            AnswerRepr::WithImage { text, image } => Self { text, image },
+        }
+    }
+}
+impl From<Answer> for AnswerRepr {
+    fn from(answer: Answer) -> Self {
+        match answer.image {
+            Some(image) => AnswerRepr::WithImage {
+                text: answer.text,
+                image: Some(image),
+            },
+            None => AnswerRepr::Text(answer.text),
+        }
+    }
+}
+
+/// How a question did in an exam, from the item analysis of the students' responses.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct ItemStats {
+    /// Number of students who got the question.
+    pub responses: u32,
+    /// Average share of the points students got, from 0 (nobody) to 1 (everybody).
+    pub difficulty: f64,
+    /// Difficulty among the best 27% of students minus among the worst 27%. Good questions are
+    /// answered correctly more often by students who did well on the whole paper.
+    pub discrimination: f64,
+    /// How many students picked each answer in `SelectionQuestion::incorrect`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub distractors: Vec<u32>,
+}
+impl ItemStats {
+    /// Distractors picked by fewer students than this share don't distract anyone.
+    const DEAD_DISTRACTOR: f64 = 0.05;
+
+    pub fn too_easy(&self) -> bool {
+        self.difficulty > 0.9
+    }
+    pub fn too_hard(&self) -> bool {
+        self.difficulty < 0.2
+    }
+    /// Indices of the incorrect answers almost nobody picked.
+    pub fn dead_distractors(&self) -> Vec<usize> {
+        let min_picks = f64::from(self.responses) * Self::DEAD_DISTRACTOR;
+        self.distractors
+            .iter()
+            .enumerate()
+            .filter(|(_, picks)| f64::from(**picks) < min_picks.max(1.0))
+            .map(|(i, _)| i)
+            .collect()
+    }
+    /// Short descriptions of what is wrong with the question, empty when nothing is.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if self.too_easy() {
+            warnings.push("Too easy".to_string());
+        }
+        if self.too_hard() {
+            warnings.push("Too hard".to_string());
+        }
+        let dead = self.dead_distractors().len();
+        if dead > 0 {
+            warnings.push(format!("{} dead distractor(s)", dead));
+        }
+        warnings
+    }
+}
+
+/// Data shared by every question type: how it is organized in the bank and what is printed next
+/// to its text.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct QuestionMeta {
+    #[serde(skip)]
+    pub tags_buf: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+    /// Title of the section the question is printed in, empty for questions outside of sections.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub section: String,
+    /// Printed below the question text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<QuestionImage>,
+    /// Results of the last item analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ItemStats>,
+}
+impl QuestionMeta {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+    pub fn update_tags_from_buf(&mut self) {
+        self.tags = self
+            .tags_buf
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+    }
+    pub fn update_buf_from_tags(&mut self) {
+        self.tags_buf = self.tags.join(", ");
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct SelectionQuestion {
     #[serde(skip)]
     pub question_buf: String,
     pub question: String,
-    pub correct: Vec<String>,
-    pub incorrect: Vec<String>,
+    pub correct: Vec<Answer>,
+    pub incorrect: Vec<Answer>,
     #[serde(default = "default_points")]
     pub points: u8,
+    #[serde(default, skip_serializing_if = "ScoringPolicy::is_all_or_nothing")]
+    pub scoring: ScoringPolicy,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
 }
 impl Default for SelectionQuestion {
     fn default() -> Self {
@@ -28,11 +317,13 @@ impl Default for SelectionQuestion {
             correct: vec![],
             incorrect: vec![],
             points: 1,
+            scoring: ScoringPolicy::default(),
+            meta: QuestionMeta::default(),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct InputQuestion {
     #[serde(skip)]
     pub question_buf: String,
@@ -40,6 +331,8 @@ pub struct InputQuestion {
     pub number_of_lines: u16,
     #[serde(default = "default_points")]
     pub points: u8,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
 }
 impl Default for InputQuestion {
     fn default() -> Self {
@@ -49,51 +342,488 @@ impl Default for InputQuestion {
             question: q,
             number_of_lines: 4,
             points: 1,
+            meta: QuestionMeta::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct TrueFalseQuestion {
+    #[serde(skip)]
+    pub question_buf: String,
+    pub question: String,
+    /// Whether the statement in `question` is true.
+    pub answer: bool,
+    #[serde(default = "default_points")]
+    pub points: u8,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
+}
+impl Default for TrueFalseQuestion {
+    fn default() -> Self {
+        let q = "New true/false statement".to_string();
+        Self {
+            question_buf: q.clone(),
+            question: q,
+            answer: true,
+            points: 1,
+            meta: QuestionMeta::default(),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct MatchingPair {
+    pub term: String,
+    pub definition: String,
+}
+impl Default for MatchingPair {
+    fn default() -> Self {
+        Self {
+            term: "Term".into(),
+            definition: "Definition".into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct MatchingQuestion {
+    #[serde(skip)]
+    pub question_buf: String,
+    pub question: String,
+    /// Terms with their matching definitions, the definitions are shuffled on the paper.
+    pub pairs: Vec<MatchingPair>,
+    #[serde(default = "default_points")]
+    pub points: u8,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
+}
+impl Default for MatchingQuestion {
+    fn default() -> Self {
+        let q = "New matching question".to_string();
+        Self {
+            question_buf: q.clone(),
+            question: q,
+            pairs: vec![],
+            points: 1,
+            meta: QuestionMeta::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ClozePart<'a> {
+    Text(&'a str),
+    /// A blank the student fills in, holding the hidden answer.
+    Blank(&'a str),
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct ClozeQuestion {
+    #[serde(skip)]
+    pub question_buf: String,
+    pub question: String,
+    /// Text with blanks marked as `{{answer}}`.
+    pub text: String,
+    #[serde(default = "default_points")]
+    pub points: u8,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
+}
+impl Default for ClozeQuestion {
+    fn default() -> Self {
+        let q = "Fill in the blanks".to_string();
+        Self {
+            question_buf: q.clone(),
+            question: q,
+            text: "The capital of France is {{Paris}}.".into(),
+            points: 1,
+            meta: QuestionMeta::default(),
+        }
+    }
+}
+impl ClozeQuestion {
+    /// Splits `text` into plain text and blanks.
+    pub fn parts(&self) -> anyhow::Result<Vec<ClozePart<'_>>> {
+        let mut parts = vec![];
+        let mut rest = self.text.as_str();
+        let mut offset = 0;
+
+        while let Some(start) = rest.find("{{") {
+            let before = &rest[..start];
+            if let Some(stray) = before.find("}}") {
+                anyhow::bail!("Unexpected '}}}}' at position {}", offset + stray);
+            }
+            if !before.is_empty() {
+                parts.push(ClozePart::Text(before));
+            }
+
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                anyhow::bail!(
+                    "Blank at position {} is missing its closing '}}}}'",
+                    offset + start
+                );
+            };
+            let answer = after[..end].trim();
+            if answer.contains("{{") {
+                anyhow::bail!(
+                    "Blank at position {} contains another blank",
+                    offset + start
+                );
+            }
+            if answer.is_empty() {
+                anyhow::bail!("Blank at position {} has no answer", offset + start);
+            }
+            parts.push(ClozePart::Blank(answer));
+
+            let consumed = start + 2 + end + 2;
+            rest = &rest[consumed..];
+            offset += consumed;
+        }
+
+        if let Some(stray) = rest.find("}}") {
+            anyhow::bail!("Unexpected '}}}}' at position {}", offset + stray);
+        }
+        if !rest.is_empty() {
+            parts.push(ClozePart::Text(rest));
+        }
+        if !parts.iter().any(|p| matches!(p, ClozePart::Blank(_))) {
+            anyhow::bail!("Text has no blanks, mark them as {{{{answer}}}}");
+        }
+
+        Ok(parts)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct OrderingQuestion {
+    #[serde(skip)]
+    pub question_buf: String,
+    pub question: String,
+    /// Items in their correct order, they are shuffled on the paper.
+    pub items: Vec<String>,
+    #[serde(default = "default_points")]
+    pub points: u8,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
+}
+impl Default for OrderingQuestion {
+    fn default() -> Self {
+        let q = "New ordering question".to_string();
+        Self {
+            question_buf: q.clone(),
+            question: q,
+            items: vec![],
+            points: 1,
+            meta: QuestionMeta::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct NumericQuestion {
+    #[serde(skip)]
+    pub question_buf: String,
+    pub question: String,
+    pub value: f64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub unit: String,
+    /// Largest accepted distance from `value`.
+    #[serde(default)]
+    pub tolerance: f64,
+    #[serde(default = "default_points")]
+    pub points: u8,
+    #[serde(flatten)]
+    pub meta: QuestionMeta,
+}
+impl Default for NumericQuestion {
+    fn default() -> Self {
+        let q = "New numeric question".to_string();
+        Self {
+            question_buf: q.clone(),
+            question: q,
+            value: 0.0,
+            unit: String::new(),
+            tolerance: 0.0,
+            points: 1,
+            meta: QuestionMeta::default(),
+        }
+    }
+}
+impl NumericQuestion {
+    pub fn accepts(&self, answer: f64) -> bool {
+        // Leave room for rounding errors, so 0.1 + 0.2 still matches 0.3 with no tolerance
+        let epsilon = 1e-9 * self.value.abs().max(1.0);
+        (answer - self.value).abs() <= self.tolerance.abs() + epsilon
+    }
+    pub fn format_answer(&self) -> String {
+        let mut answer = self.value.to_string();
+        if self.tolerance != 0.0 {
+            answer = format!("{} ± {}", answer, self.tolerance.abs());
+        }
+        if !self.unit.is_empty() {
+            answer = format!("{} {}", answer, self.unit);
+        }
+        answer
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Question {
     Selection(SelectionQuestion),
     Input(InputQuestion),
+    TrueFalse(TrueFalseQuestion),
+    Matching(MatchingQuestion),
+    Cloze(ClozeQuestion),
+    Ordering(OrderingQuestion),
+    Numeric(NumericQuestion),
 }
 impl Question {
     pub fn get_title(&self) -> String {
         match self {
             Question::Selection(q) => q.question.clone(),
             Question::Input(q) => q.question.clone(),
+            Question::TrueFalse(q) => q.question.clone(),
+            Question::Matching(q) => q.question.clone(),
+            Question::Cloze(q) => q.question.clone(),
+            Question::Ordering(q) => q.question.clone(),
+            Question::Numeric(q) => q.question.clone(),
         }
     }
+    /// The first line of the title, for places where code blocks and line breaks don't fit.
+    pub fn get_short_title(&self) -> String {
+        let title = self.get_title();
+        title
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("```"))
+            .unwrap_or_default()
+            .to_string()
+    }
     pub fn get_title_buf(&mut self) -> &mut String {
         match self {
             Question::Selection(q) => &mut q.question_buf,
             Question::Input(q) => &mut q.question_buf,
+            Question::TrueFalse(q) => &mut q.question_buf,
+            Question::Matching(q) => &mut q.question_buf,
+            Question::Cloze(q) => &mut q.question_buf,
+            Question::Ordering(q) => &mut q.question_buf,
+            Question::Numeric(q) => &mut q.question_buf,
         }
     }
     pub fn update_title_from_buf(&mut self) {
         match self {
             Question::Selection(q) => q.question = q.question_buf.clone(),
             Question::Input(q) => q.question = q.question_buf.clone(),
+            Question::TrueFalse(q) => q.question = q.question_buf.clone(),
+            Question::Matching(q) => q.question = q.question_buf.clone(),
+            Question::Cloze(q) => q.question = q.question_buf.clone(),
+            Question::Ordering(q) => q.question = q.question_buf.clone(),
+            Question::Numeric(q) => q.question = q.question_buf.clone(),
         }
     }
     pub fn update_buf_from_title(&mut self) {
         match self {
             Question::Selection(q) => q.question_buf = q.question.clone(),
             Question::Input(q) => q.question_buf = q.question.clone(),
+            Question::TrueFalse(q) => q.question_buf = q.question.clone(),
+            Question::Matching(q) => q.question_buf = q.question.clone(),
+            Question::Cloze(q) => q.question_buf = q.question.clone(),
+            Question::Ordering(q) => q.question_buf = q.question.clone(),
+            Question::Numeric(q) => q.question_buf = q.question.clone(),
         }
     }
+    pub fn get_type(&self) -> QuestionType {
+        match self {
+            Question::Selection(_) => QuestionType::Selection,
+            Question::Input(_) => QuestionType::Input,
+            Question::TrueFalse(_) => QuestionType::TrueFalse,
+            Question::Matching(_) => QuestionType::Matching,
+            Question::Cloze(_) => QuestionType::Cloze,
+            Question::Ordering(_) => QuestionType::Ordering,
+            Question::Numeric(_) => QuestionType::Numeric,
+        }
+    }
+    /// The title and the text of every answer.
+    pub fn get_texts(&self) -> Vec<&str> {
+        let (title, answers): (&str, Vec<&str>) = match self {
+            Question::Selection(q) => (
+                &q.question,
+                q.correct
+                    .iter()
+                    .chain(q.incorrect.iter())
+                    .map(|a| a.text.as_str())
+                    .collect(),
+            ),
+            Question::Input(q) => (&q.question, vec![]),
+            Question::TrueFalse(q) => (&q.question, vec![]),
+            Question::Matching(q) => (
+                &q.question,
+                q.pairs
+                    .iter()
+                    .flat_map(|p| [p.term.as_str(), p.definition.as_str()])
+                    .collect(),
+            ),
+            Question::Cloze(q) => (&q.question, vec![q.text.as_str()]),
+            Question::Ordering(q) => (&q.question, q.items.iter().map(String::as_str).collect()),
+            Question::Numeric(q) => (&q.question, vec![q.unit.as_str()]),
+        };
+        std::iter::once(title).chain(answers).collect()
+    }
+    /// Whether `query` appears in the title or an answer, ignoring case. A fuzzy search also
+    /// matches when the characters of the query appear in order with others between them.
+    pub fn matches_search(&self, query: &str, fuzzy: bool) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+
+        self.get_texts().iter().any(|text| {
+            let text = text.to_lowercase();
+            if text.contains(&query) {
+                return true;
+            }
+            let mut chars = text.chars();
+            fuzzy
+                && query
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .all(|c| chars.any(|t| t == c))
+        })
+    }
     pub fn get_points(&self) -> u8 {
         match self {
             Question::Selection(q) => q.points,
             Question::Input(q) => q.points,
+            Question::TrueFalse(q) => q.points,
+            Question::Matching(q) => q.points,
+            Question::Cloze(q) => q.points,
+            Question::Ordering(q) => q.points,
+            Question::Numeric(q) => q.points,
+        }
+    }
+    pub fn meta(&self) -> &QuestionMeta {
+        match self {
+            Question::Selection(q) => &q.meta,
+            Question::Input(q) => &q.meta,
+            Question::TrueFalse(q) => &q.meta,
+            Question::Matching(q) => &q.meta,
+            Question::Cloze(q) => &q.meta,
+            Question::Ordering(q) => &q.meta,
+            Question::Numeric(q) => &q.meta,
+        }
+    }
+    /// Checks the parts of the question that are parsed from text.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        markup::validate(&self.get_title())?;
+        let check =
+            |text: &str| markup::validate(text).with_context(|| format!("Answer '{}'", text));
+        match self {
+            Question::Selection(q) => {
+                for answer in q.correct.iter().chain(q.incorrect.iter()) {
+                    check(&answer.text)?;
+                }
+            }
+            Question::Matching(q) => {
+                for pair in q.pairs.iter() {
+                    check(&pair.term)?;
+                    check(&pair.definition)?;
+                }
+            }
+            Question::Ordering(q) => {
+                for item in q.items.iter() {
+                    check(item)?;
+                }
+            }
+            Question::Cloze(q) => {
+                q.parts()?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    pub fn meta_mut(&mut self) -> &mut QuestionMeta {
+        match self {
+            Question::Selection(q) => &mut q.meta,
+            Question::Input(q) => &mut q.meta,
+            Question::TrueFalse(q) => &mut q.meta,
+            Question::Matching(q) => &mut q.meta,
+            Question::Cloze(q) => &mut q.meta,
+            Question::Ordering(q) => &mut q.meta,
+            Question::Numeric(q) => &mut q.meta,
+        }
+    }
+}
+
+/// Draws `count` questions matching `tag` and `difficulty` from the question bank. Empty
+/// filters match every question.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct BlueprintRule {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tag: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+    pub count: u8,
+}
+impl Default for BlueprintRule {
+    fn default() -> Self {
+        Self {
+            tag: String::new(),
+            difficulty: None,
+            count: 1,
+        }
+    }
+}
+impl BlueprintRule {
+    pub fn matches(&self, question: &Question) -> bool {
+        let meta = question.meta();
+        (self.tag.is_empty() || meta.has_tag(&self.tag))
+            && (self.difficulty.is_none() || meta.difficulty == self.difficulty)
+    }
+    pub fn describe(&self) -> String {
+        let difficulty = self.difficulty.map(|d| d.get_name().to_lowercase());
+        let tag = (!self.tag.is_empty()).then(|| format!("from '{}'", self.tag));
+        let filters: Vec<String> = difficulty.into_iter().chain(tag).collect();
+
+        if filters.is_empty() {
+            format!("{} questions", self.count)
+        } else {
+            format!("{} {}", self.count, filters.join(" "))
+        }
+    }
+}
+
+/// A titled part of the paper, e.g. "Part I - Multiple choice". Questions outside of sections are
+/// printed before the first section.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Section {
+    #[serde(skip)]
+    pub title_buf: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub instructions: String,
+    #[serde(default)]
+    pub show_subtotal: bool,
+    #[serde(default)]
+    pub shuffle: bool,
+}
+impl Default for Section {
+    fn default() -> Self {
+        let title = "New section".to_string();
+        Self {
+            title_buf: title.clone(),
+            title,
+            instructions: String::new(),
+            show_subtotal: false,
+            shuffle: false,
         }
     }
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Header {
     pub title: String,
 }
@@ -117,8 +847,32 @@ pub enum OpenedTab {
 pub struct GuiState {
     pub opened_tab: OpenedTab,
     pub selected_question: usize,
+    pub show_preview: bool,
+    pub preview_page: usize,
+    pub search: String,
+    pub fuzzy_search: bool,
+    pub type_filter: Option<QuestionType>,
+    pub points_filter: Option<u8>,
     #[serde(skip)]
     pub toasts: Arc<Mutex<Toasts>>,
+    /// Thumbnails of images in the editor by path, `None` when the file could not be loaded.
+    #[serde(skip)]
+    pub thumbnails: preview::Textures,
+    /// The file the project was opened from or last saved to.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// Recently opened and saved projects, newest first. Kept between sessions by eframe.
+    #[serde(skip)]
+    pub recent_projects: Vec<PathBuf>,
+    #[serde(skip)]
+    pub window_title: String,
+    #[serde(skip)]
+    pub history: History,
+    #[serde(skip)]
+    pub preview: Option<preview::Cache>,
+    /// Images drawn in the preview by path, loaded bigger than the thumbnails.
+    #[serde(skip)]
+    pub preview_images: preview::Textures,
 }
 
 #[allow(dead_code)]
@@ -126,7 +880,270 @@ pub struct GuiState {
 pub struct Project {
     pub settings: Settings,
     pub header: Header,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<Section>,
     pub questions: Vec<Question>,
+    /// When not empty, questions are drawn from the bank to satisfy these rules instead of taking
+    /// the first `max_questions`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blueprint: Vec<BlueprintRule>,
     #[serde(skip)]
     pub gui_state: GuiState,
 }
+impl Project {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read project file {}", path.display()))?;
+        let mut project: Project = toml::from_str(&content)
+            .with_context(|| format!("Could not parse project file {}", path.display()))?;
+
+        for q in project.questions.iter_mut() {
+            q.update_buf_from_title();
+            q.meta_mut().update_buf_from_tags();
+        }
+        for section in project.sections.iter_mut() {
+            section.title_buf = section.title.clone();
+        }
+
+        project
+            .validate()
+            .with_context(|| format!("Invalid project file {}", path.display()))?;
+        Ok(project)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let content = toml::to_string(self)?;
+        fs::write(path, content)
+            .with_context(|| format!("Could not write project file {}", path.display()))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        // Questions find their section by its title
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.title.trim().is_empty() {
+                anyhow::bail!("Section {} has no title", i + 1);
+            }
+            if self.sections[..i].iter().any(|s| s.title == section.title) {
+                anyhow::bail!("There is more than one section titled '{}'", section.title);
+            }
+        }
+        for (i, q) in self.questions.iter().enumerate() {
+            q.validate()
+                .with_context(|| format!("Question {} ({})", i + 1, q.get_short_title()))?;
+        }
+        Ok(())
+    }
+
+    /// Adds a section with a title no other section has.
+    pub fn add_section(&mut self) {
+        let mut section = Section::default();
+        let taken = |title: &str| self.sections.iter().any(|s| s.title == title);
+        if taken(&section.title) {
+            let n = (2..).find(|n| !taken(&format!("{} {}", section.title, n)));
+            section.title = format!("{} {}", section.title, n.unwrap_or_default());
+            section.title_buf = section.title.clone();
+        }
+        self.sections.push(section);
+    }
+
+    /// Renames a section to the title being edited and moves its questions along with it. An
+    /// empty title or one taken by another section is refused, since questions find their section
+    /// by its title.
+    pub fn rename_section(&mut self, idx: usize) -> anyhow::Result<()> {
+        let title = self.sections[idx].title_buf.trim().to_string();
+        let taken = self
+            .sections
+            .iter()
+            .enumerate()
+            .any(|(i, s)| i != idx && s.title == title);
+        let section = &mut self.sections[idx];
+        if title.is_empty() || taken {
+            section.title_buf = section.title.clone();
+            if title.is_empty() {
+                anyhow::bail!("A section needs a title");
+            }
+            anyhow::bail!("There already is a section titled '{}'", title);
+        }
+
+        section.title_buf = title.clone();
+        let old = std::mem::replace(&mut section.title, title);
+
+        for q in self.questions.iter_mut() {
+            let meta = q.meta_mut();
+            if meta.section == old {
+                meta.section = self.sections[idx].title.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a section, its questions are moved out of sections.
+    pub fn remove_section(&mut self, idx: usize) {
+        let section = self.sections.remove(idx);
+
+        for q in self.questions.iter_mut() {
+            let meta = q.meta_mut();
+            if meta.section == section.title {
+                meta.section.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloze(text: &str) -> ClozeQuestion {
+        ClozeQuestion {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cloze_parts() {
+        let question = cloze("The capital of {{France}} is {{ Paris }}.");
+        assert_eq!(
+            question.parts().unwrap(),
+            vec![
+                ClozePart::Text("The capital of "),
+                ClozePart::Blank("France"),
+                ClozePart::Text(" is "),
+                ClozePart::Blank("Paris"),
+                ClozePart::Text("."),
+            ]
+        );
+        assert_eq!(
+            cloze("{{a}}{{b}}").parts().unwrap(),
+            vec![ClozePart::Blank("a"), ClozePart::Blank("b")]
+        );
+    }
+
+    #[test]
+    fn cloze_parts_unbalanced() {
+        assert!(cloze("Missing {{end").parts().is_err());
+        assert!(cloze("Missing start}} here {{a}}").parts().is_err());
+        assert!(cloze("{{a}} and a stray}}").parts().is_err());
+        assert!(cloze("{{outer {{inner}}").parts().is_err());
+    }
+
+    #[test]
+    fn cloze_parts_empty() {
+        assert!(cloze("Nothing to fill in").parts().is_err());
+        assert!(cloze("").parts().is_err());
+        assert!(cloze("An empty {{}} blank").parts().is_err());
+        assert!(cloze("A blank of spaces {{   }}").parts().is_err());
+    }
+
+    #[test]
+    fn all_or_nothing() {
+        let policy = ScoringPolicy::AllOrNothing;
+        assert_eq!(policy.score(2.0, 2, 2, 0), 2.0);
+        assert_eq!(policy.score(2.0, 2, 1, 0), 0.0);
+        assert_eq!(policy.score(2.0, 2, 2, 1), 0.0);
+        assert_eq!(policy.score(2.0, 2, 0, 0), 0.0);
+    }
+
+    #[test]
+    fn proportional() {
+        let policy = ScoringPolicy::Proportional;
+        assert_eq!(policy.score(2.0, 2, 2, 0), 2.0);
+        assert_eq!(policy.score(2.0, 2, 1, 0), 1.0);
+        assert_eq!(policy.score(3.0, 3, 2, 0), 2.0);
+        assert_eq!(
+            policy.score(2.0, 2, 1, 3),
+            1.0,
+            "incorrect answers are ignored"
+        );
+        assert_eq!(policy.score(2.0, 2, 0, 0), 0.0);
+    }
+
+    #[test]
+    fn penalty() {
+        let policy = |floor_at_zero| ScoringPolicy::Penalty {
+            penalty: 0.5,
+            floor_at_zero,
+        };
+        for floor_at_zero in [false, true] {
+            assert_eq!(policy(floor_at_zero).score(2.0, 2, 2, 0), 2.0);
+            assert_eq!(policy(floor_at_zero).score(2.0, 2, 1, 0), 1.0);
+            assert_eq!(policy(floor_at_zero).score(2.0, 2, 2, 1), 1.5);
+            assert_eq!(policy(floor_at_zero).score(2.0, 2, 1, 2), 0.0);
+        }
+        assert_eq!(policy(false).score(2.0, 2, 0, 1), -0.5);
+        assert_eq!(policy(true).score(2.0, 2, 0, 1), 0.0);
+        assert_eq!(policy(false).score(2.0, 2, 1, 3), -0.5);
+        assert_eq!(policy(true).score(2.0, 2, 1, 3), 0.0);
+    }
+
+    #[test]
+    fn no_correct_answers() {
+        // The only right response is to choose nothing
+        let penalty = ScoringPolicy::Penalty {
+            penalty: 0.5,
+            floor_at_zero: false,
+        };
+        for policy in [
+            ScoringPolicy::AllOrNothing,
+            ScoringPolicy::Proportional,
+            penalty,
+        ] {
+            assert_eq!(policy.score(2.0, 0, 0, 0), 2.0);
+        }
+        assert_eq!(ScoringPolicy::AllOrNothing.score(2.0, 0, 0, 1), 0.0);
+        assert_eq!(ScoringPolicy::Proportional.score(2.0, 0, 0, 1), 0.0);
+        assert_eq!(penalty.score(2.0, 0, 0, 2), -1.0);
+    }
+
+    fn titles(project: &Project) -> Vec<&str> {
+        project.sections.iter().map(|s| s.title.as_str()).collect()
+    }
+
+    #[test]
+    fn section_titles_are_unique() {
+        let mut project = Project::default();
+        for _ in 0..3 {
+            project.add_section();
+        }
+        assert_eq!(
+            titles(&project),
+            vec!["New section", "New section 2", "New section 3"]
+        );
+        assert!(project.validate().is_ok());
+
+        let mut question = InputQuestion::default();
+        question.meta.section = "New section 2".into();
+        project.questions.push(Question::Input(question));
+
+        // Taken and empty titles are refused and the edit is undone
+        for title in ["New section", " "] {
+            project.sections[1].title_buf = title.into();
+            assert!(project.rename_section(1).is_err());
+            assert_eq!(project.sections[1].title_buf, "New section 2");
+        }
+        assert_eq!(titles(&project)[1], "New section 2");
+
+        project.sections[1].title_buf = " Part II ".into();
+        project.rename_section(1).unwrap();
+        assert_eq!(titles(&project)[1], "Part II");
+        assert_eq!(project.questions[0].meta().section, "Part II");
+    }
+
+    #[test]
+    fn validate_rejects_bad_section_titles() {
+        let mut project = Project {
+            sections: vec![Section::default(), Section::default()],
+            ..Default::default()
+        };
+        assert!(project.validate().is_err());
+
+        project.sections[1].title = "Part II".into();
+        assert!(project.validate().is_ok());
+
+        project.sections[1].title = String::new();
+        assert!(project.validate().is_err());
+    }
+}
@@ -1,20 +1,52 @@
-use std::{fs::File, io::Write, time::Duration};
+use std::{path::PathBuf, time::Duration};
 
 use crate::{
-    data::{GuiState, OpenedTab, Project, Question},
+    data::{
+        Answer, BlueprintRule, Difficulty, GuiState, ImageAlignment, ItemStats, MatchingPair,
+        OpenedTab, Project, Question, QuestionImage, QuestionType, ScoringPolicy,
+    },
+    history::Edit,
+    markup,
     pdf_gen::generate_pdf,
+    preview,
     settings::{
+        Grade, GradingScale,
         Language::{Bulgarian, English},
         PaperSize,
     },
 };
-use egui::{ScrollArea, TextStyle, Ui};
+use egui::{Key, Modifiers, ScrollArea, TextStyle, Ui};
+
+const APP_NAME: &str = "Test Generator";
+const RECENT_PROJECTS_KEY: &str = "recent_projects";
+const MAX_RECENT_PROJECTS: usize = 10;
 
-pub fn run_gui(project: Project) {
+pub fn run_gui(mut project: Project) {
     let options = eframe::NativeOptions::default();
 
-    eframe::run_native("xd", options, Box::new(|_| Box::new(project)))
-        .expect("eframe failed to start");
+    eframe::run_native(
+        APP_NAME,
+        options,
+        Box::new(|cc| {
+            if let Some(storage) = cc.storage {
+                project.gui_state.recent_projects =
+                    eframe::get_value(storage, RECENT_PROJECTS_KEY).unwrap_or_default();
+            }
+            if let Some(path) = project.gui_state.path.clone() {
+                project.set_path(path);
+            }
+            Box::new(project)
+        }),
+    )
+    .expect("eframe failed to start");
+}
+
+fn project_dialog(path: Option<&PathBuf>) -> rfd::FileDialog {
+    let dialog = rfd::FileDialog::new().add_filter("Project", &["toml"]);
+    match path.and_then(|p| p.parent()) {
+        Some(dir) if !dir.as_os_str().is_empty() => dialog.set_directory(dir),
+        _ => dialog,
+    }
 }
 
 fn add_label(label: &str, ui: &mut Ui) {
@@ -30,31 +62,367 @@ fn add_label(label: &str, ui: &mut Ui) {
     ui.add_space(4.0);
 }
 
-fn add_answers(gui_state: &mut GuiState, answers: &mut Vec<String>, ui: &mut Ui) {
+fn difficulty_combo(id: &str, difficulty: &mut Option<Difficulty>, empty: &str, ui: &mut Ui) {
+    ui.push_id(id, |ui| {
+        egui::ComboBox::from_label("")
+            .selected_text(difficulty.map_or(empty, |d| d.get_name()))
+            .show_ui(ui, |ui| {
+                ui.style_mut().wrap = Some(false);
+                ui.set_min_width(60.0);
+                ui.selectable_value(difficulty, None, empty);
+                for d in Difficulty::ALL {
+                    ui.selectable_value(difficulty, Some(d), d.get_name());
+                }
+            });
+    });
+}
+
+fn scoring_policy(scoring: &mut ScoringPolicy, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Scoring");
+        ui.push_id("scoring_combobox", |ui| {
+            egui::ComboBox::from_label("")
+                .selected_text(scoring.get_name())
+                .show_ui(ui, |ui| {
+                    ui.style_mut().wrap = Some(false);
+                    ui.set_min_width(60.0);
+                    ui.selectable_value(scoring, ScoringPolicy::AllOrNothing, "All or nothing")
+                        .on_hover_text("Points only for choosing exactly the correct answers");
+                    ui.selectable_value(scoring, ScoringPolicy::Proportional, "Proportional")
+                        .on_hover_text("A share of the points for every correct answer chosen");
+                    if ui
+                        .selectable_label(
+                            matches!(scoring, ScoringPolicy::Penalty { .. }),
+                            "Penalty",
+                        )
+                        .on_hover_text("Proportional, minus points for every wrong answer chosen")
+                        .clicked()
+                        && !matches!(scoring, ScoringPolicy::Penalty { .. })
+                    {
+                        *scoring = ScoringPolicy::Penalty {
+                            penalty: 0.5,
+                            floor_at_zero: true,
+                        };
+                    }
+                });
+        });
+    });
+
+    if let ScoringPolicy::Penalty {
+        penalty,
+        floor_at_zero,
+    } = scoring
+    {
+        ui.horizontal(|ui| {
+            ui.label("Penalty per wrong answer");
+            ui.add(
+                egui::DragValue::new(penalty)
+                    .speed(0.05)
+                    .clamp_range(0.0..=8.0),
+            );
+            ui.checkbox(floor_at_zero, "Not below 0");
+        });
+    }
+}
+
+/// Edits the items of an ordering question. Unlike the answers of a selection question, there can
+/// be any number of them.
+fn add_items(items: &mut Vec<String>, ui: &mut Ui) {
     ui.vertical(|ui| {
-        for i in 0..answers.len() {
+        for i in 0..items.len() {
             ui.horizontal(|ui| {
-                if let Some(q) = answers.get_mut(i) {
-                    _ = egui::TextEdit::singleline(q).show(ui);
+                if let Some(item) = items.get_mut(i) {
+                    _ = egui::TextEdit::singleline(item).show(ui);
                 }
 
                 if ui.button("Remove").clicked() {
-                    answers.remove(i);
+                    items.remove(i);
                 }
             });
         }
+        if ui.button("Add").clicked() {
+            items.push("New Item".to_string());
+        }
+    });
+}
+
+fn image_thumbnail(thumbnails: &mut preview::Textures, path: &str, ui: &mut Ui) {
+    match preview::load_texture(thumbnails, path, 128, ui.ctx()) {
+        Some(texture) => {
+            ui.image(texture.id(), texture.size_vec2());
+        }
+        None => {
+            ui.colored_label(ui.visuals().error_fg_color, "Could not load image");
+        }
+    }
+}
+
+fn edit_image(gui_state: &mut GuiState, image: &mut Option<QuestionImage>, ui: &mut Ui) {
+    let mut has_image = image.is_some();
+    if ui.checkbox(&mut has_image, "Image").changed() {
+        *image = has_image.then(QuestionImage::default);
+    }
+    let Some(image) = image else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Path     ");
+        egui::TextEdit::singleline(&mut image.path)
+            .hint_text("images/diagram.png")
+            .show(ui);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Width    ");
+        let mut original = image.width.is_none();
+        if ui.checkbox(&mut original, "Original size").changed() {
+            image.width = (!original).then_some(60.0);
+        }
+        if let Some(width) = &mut image.width {
+            ui.add(egui::Slider::new(width, 5.0..=190.0).suffix("mm"));
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Align    ");
+        for alignment in ImageAlignment::ALL {
+            ui.selectable_value(&mut image.alignment, alignment, alignment.get_name());
+        }
+    });
+
+    if !image.path.is_empty() {
+        image_thumbnail(&mut gui_state.thumbnails, &image.path, ui);
+    }
+}
+
+/// Shows the results of the last item analysis of a question, if it had one.
+fn draw_stats(question: &Question, ui: &mut Ui) {
+    let Some(stats) = &question.meta().stats else {
+        return;
+    };
+
+    for warning in stats.warnings() {
+        ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {}", warning));
+    }
+    ui.collapsing("Item analysis", |ui| {
+        ui.label(format!("Responses: {}", stats.responses));
+        ui.label(format!("Difficulty: {:.2}", stats.difficulty))
+            .on_hover_text("Average share of the points students got, 1 means everybody");
+        ui.label(format!("Discrimination: {:.2}", stats.discrimination))
+            .on_hover_text("How much better the best students did than the worst ones");
+
+        if let Question::Selection(q) = question {
+            let dead = stats.dead_distractors();
+            for (i, (answer, picks)) in q.incorrect.iter().zip(&stats.distractors).enumerate() {
+                let text = format!("{}: picked {} times", answer.text, picks);
+                if dead.contains(&i) {
+                    ui.colored_label(ui.visuals().warn_fg_color, text);
+                } else {
+                    ui.label(text);
+                }
+            }
+        }
+    });
+}
+
+/// Lets the rows of a list be dragged to a new place by their handles. `rows` holds the
+/// rectangle of every row with the response of its handle. Returns where a row was moved from and
+/// to when it is dropped.
+fn reorder(ui: &Ui, id: egui::Id, rows: &[(egui::Rect, egui::Response)]) -> Option<(usize, usize)> {
+    if let Some(i) = rows.iter().position(|(_, handle)| handle.drag_started()) {
+        ui.memory_mut(|memory| memory.data.insert_temp(id, i));
+    }
+    for (_, handle) in rows.iter() {
+        if handle.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+    }
+
+    let from: usize = ui.memory_mut(|memory| memory.data.get_temp(id))?;
+    let (first, last) = (rows.first()?.0, rows.last()?.0);
+    let (down, released) =
+        ui.input(|input| (input.pointer.any_down(), input.pointer.any_released()));
+    let pointer = ui.ctx().pointer_interact_pos();
+    let Some(pointer) = pointer.filter(|_| (down || released) && from < rows.len()) else {
+        // The drag ended somewhere this list wasn't drawn
+        ui.memory_mut(|memory| memory.data.remove::<usize>(id));
+        return None;
+    };
+
+    // The row goes before the first row whose middle is below the pointer
+    let target = rows
+        .iter()
+        .filter(|(rect, _)| rect.center().y < pointer.y)
+        .count();
+    let y = rows
+        .get(target)
+        .map_or(last.bottom(), |(rect, _)| rect.top());
+    ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+    ui.painter()
+        .hline(first.x_range(), y, ui.visuals().selection.stroke);
+
+    if !released {
+        return None;
+    }
+    ui.memory_mut(|memory| memory.data.remove::<usize>(id));
+    let to = if target > from { target - 1 } else { target };
+    (to != from).then_some((from, to))
+}
+
+fn add_selection_answers(gui_state: &mut GuiState, answers: &mut Vec<Answer>, ui: &mut Ui) {
+    ui.vertical(|ui| {
+        let mut remove = None;
+        let mut rows = vec![];
+        for (i, answer) in answers.iter_mut().enumerate() {
+            let row = ui.push_id(i, |ui| {
+                let handle = ui
+                    .horizontal(|ui| {
+                        let handle = ui
+                            .add(egui::Label::new("≡").sense(egui::Sense::drag()))
+                            .on_hover_text("Drag to reorder");
+                        _ = egui::TextEdit::multiline(&mut answer.text)
+                            .desired_rows(1)
+                            .show(ui);
+
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        handle
+                    })
+                    .inner;
+                ui.indent("answer_image", |ui| {
+                    edit_image(gui_state, &mut answer.image, ui)
+                });
+                handle
+            });
+            rows.push((row.response.rect, row.inner));
+        }
+        if let Some((from, to)) = reorder(ui, ui.id().with("answers"), &rows) {
+            let answer = answers.remove(from);
+            answers.insert(to, answer);
+        }
+        if let Some(i) = remove {
+            answers.remove(i);
+        }
         if ui.button("Add").clicked() {
             if answers.len() >= 6 {
                 let mut toasts = gui_state.toasts.lock().unwrap();
                 toasts.error("Too many answers");
                 return;
             }
-            answers.push("New Answer".to_string());
+            answers.push(Answer::from("New Answer"));
+        }
+    });
+}
+
+fn add_pairs(pairs: &mut Vec<MatchingPair>, ui: &mut Ui) {
+    ui.vertical(|ui| {
+        let mut remove = None;
+        for (i, pair) in pairs.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut pair.term)
+                    .desired_width(120.0)
+                    .show(ui);
+                ui.label("->");
+                egui::TextEdit::singleline(&mut pair.definition).show(ui);
+
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            pairs.remove(i);
+        }
+        if ui.button("Add").clicked() {
+            pairs.push(MatchingPair::default());
         }
     });
 }
 
 impl Project {
+    /// Remembers where the project lives and moves it to the top of the recent projects.
+    fn set_path(&mut self, path: PathBuf) {
+        let recent = &mut self.gui_state.recent_projects;
+        recent.retain(|p| *p != path);
+        recent.insert(0, path.clone());
+        recent.truncate(MAX_RECENT_PROJECTS);
+        self.gui_state.path = Some(path);
+    }
+
+    /// Replaces the project with the one at `path`, keeping the state of the editor itself.
+    fn open(&mut self, path: PathBuf) {
+        match Project::load(&path) {
+            Ok(mut project) => {
+                project.gui_state.toasts = self.gui_state.toasts.clone();
+                project.gui_state.recent_projects =
+                    std::mem::take(&mut self.gui_state.recent_projects);
+                project.gui_state.window_title = std::mem::take(&mut self.gui_state.window_title);
+                project.gui_state.history.replace(&self.gui_state.history);
+                *self = project;
+                self.set_path(path);
+
+                let mut toasts = self.gui_state.toasts.lock().unwrap();
+                toasts.success("Project opened");
+            }
+            Err(err) => {
+                let mut toasts = self.gui_state.toasts.lock().unwrap();
+                toasts
+                    .error(format!("{:#}", err))
+                    .set_duration(Some(Duration::from_secs(10)));
+            }
+        }
+    }
+
+    fn save_to(&mut self, path: PathBuf) {
+        match self.save(&path) {
+            Ok(()) => {
+                self.set_path(path);
+                let mut toasts = self.gui_state.toasts.lock().unwrap();
+                toasts.success("Project saved");
+            }
+            Err(err) => {
+                let mut toasts = self.gui_state.toasts.lock().unwrap();
+                toasts
+                    .error(format!("{:#}", err))
+                    .set_duration(Some(Duration::from_secs(10)));
+            }
+        }
+    }
+
+    fn save_as(&mut self) {
+        let path = project_dialog(self.gui_state.path.as_ref())
+            .set_file_name("project.toml")
+            .save_file();
+        if let Some(path) = path {
+            self.save_to(path);
+        }
+    }
+
+    /// Moves the selected question `offset` places up or down the list, if it can go there.
+    fn move_selected_question(&mut self, offset: isize) {
+        let from = self.gui_state.selected_question;
+        let Some(to) = from.checked_add_signed(offset) else {
+            return;
+        };
+        if from < self.questions.len() && to < self.questions.len() {
+            self.edit(Edit::MoveQuestion { from, to });
+        }
+    }
+
+    fn add_question(&mut self, question: Question) {
+        self.edit(Edit::InsertQuestion {
+            index: self.questions.len(),
+            question: Box::new(question),
+        });
+    }
+
+    fn window_title(&self) -> String {
+        match &self.gui_state.path {
+            Some(path) => format!("{} - {}", path.display(), APP_NAME),
+            None => APP_NAME.to_string(),
+        }
+    }
+
     fn draw_topbar(&mut self, ui: &mut Ui) {
         ui.horizontal_wrapped(|ui| {
             ui.selectable_value(
@@ -73,13 +441,32 @@ impl Project {
                 "Settings",
             );
 
+            ui.separator();
+            let history = &self.gui_state.history;
+            let (can_undo, can_redo) = (history.can_undo(), history.can_redo());
+            if ui
+                .add_enabled(can_undo, egui::Button::new("Undo"))
+                .on_hover_text("Ctrl+Z")
+                .clicked()
+            {
+                self.undo();
+            }
+            if ui
+                .add_enabled(can_redo, egui::Button::new("Redo"))
+                .on_hover_text("Ctrl+Shift+Z")
+                .clicked()
+            {
+                self.redo();
+            }
+            ui.separator();
+            ui.checkbox(&mut self.gui_state.show_preview, "Preview");
             ui.separator();
 
             if ui.button("Generate PDF").clicked() {
                 let mut toasts = self.gui_state.toasts.lock().unwrap();
 
                 match generate_pdf(self) {
-                    Ok(dur) => toasts
+                    Ok((_, dur)) => toasts
                         .success(format!("PDF was generated in {:.3}sec", dur.as_secs_f64()))
                         .set_duration(Some(Duration::from_secs(2))),
                     Err(err) => toasts
@@ -87,35 +474,186 @@ impl Project {
                         .set_duration(Some(Duration::from_secs(10))),
                 };
             }
-            if ui.button("Save Project").clicked() {
-                let mut project_file = File::create("project.toml").unwrap();
-                let str = toml::to_string(self).unwrap();
-
-                let mut toasts = self.gui_state.toasts.lock().unwrap();
-                match project_file.write(str.as_bytes()) {
-                    Ok(_) => toasts.success("Project saved"),
-                    Err(err) => toasts.error(&format!("{}", err)),
-                };
-            }
             if ui.button("Open Project").clicked() {
-                let content = std::fs::read_to_string("project.toml").unwrap();
-                *self = toml::from_str(&content).unwrap();
-                for q in self.questions.iter_mut() {
-                    q.update_buf_from_title();
+                if let Some(path) = project_dialog(self.gui_state.path.as_ref()).pick_file() {
+                    self.open(path);
                 }
-                let mut toasts = self.gui_state.toasts.lock().unwrap();
-                toasts.success("Project opened");
+            }
+            if ui.button("Save Project").clicked() {
+                match self.gui_state.path.clone() {
+                    Some(path) => self.save_to(path),
+                    None => self.save_as(),
+                }
+            }
+            if ui.button("Save As").clicked() {
+                self.save_as();
+            }
+
+            let mut open = None;
+            ui.add_enabled_ui(!self.gui_state.recent_projects.is_empty(), |ui| {
+                ui.menu_button("Recent", |ui| {
+                    for path in self.gui_state.recent_projects.iter() {
+                        if ui.button(path.display().to_string()).clicked() {
+                            open = Some(path.clone());
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            if let Some(path) = open {
+                self.open(path);
             }
         });
         ui.end_row();
     }
 
+    /// Draws one page of the paper as it will roughly be printed.
+    fn draw_preview(&mut self, ui: &mut Ui) {
+        let page_size = preview::page_size(self);
+        // Leave room for the page navigation above the page
+        let available = ui.available_size() - egui::vec2(0.0, 30.0);
+        let scale = (available.x / page_size.x)
+            .min(available.y / page_size.y)
+            .max(0.5);
+
+        // Laying out the paper reads its images, so only do it again when something changed
+        let cache = match self.gui_state.preview.take() {
+            Some(cache) if cache.is_for(self, ui, scale) => cache,
+            _ => {
+                let mut textures = std::mem::take(&mut self.gui_state.preview_images);
+                let cache = preview::Cache::new(self, &mut textures, ui, scale);
+                self.gui_state.preview_images = textures;
+                cache
+            }
+        };
+        let cache = self.gui_state.preview.insert(cache);
+        let pages = match &cache.pages {
+            Ok(pages) => pages,
+            Err(err) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+                return;
+            }
+        };
+
+        let random = self.settings.seed.is_none();
+        let page = &mut self.gui_state.preview_page;
+        *page = (*page).min(pages.len() - 1);
+        ui.horizontal(|ui| {
+            if ui.add_enabled(*page > 0, egui::Button::new("◀")).clicked() {
+                *page -= 1;
+            }
+            ui.label(format!("Page {} / {}", *page + 1, pages.len()));
+            if ui
+                .add_enabled(*page + 1 < pages.len(), egui::Button::new("▶"))
+                .clicked()
+            {
+                *page += 1;
+            }
+            if random {
+                ui.weak("Random order").on_hover_text(
+                    "No seed is set, so every generated paper gets an order of its own",
+                );
+            }
+        })
+        .response
+        .on_hover_text("An approximation, the PDF can break lines and pages a bit differently");
+
+        let (rect, _) = ui.allocate_exact_size(page_size * scale, egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::WHITE);
+        for shape in pages[*page].iter() {
+            let mut shape = shape.clone();
+            shape.translate(rect.min.to_vec2());
+            painter.add(shape);
+        }
+    }
+
+    /// Indices of the questions that pass the search and the filters.
+    fn visible_questions(&self) -> Vec<usize> {
+        let state = &self.gui_state;
+        self.questions
+            .iter()
+            .enumerate()
+            .filter(|(_, q)| state.type_filter.is_none_or(|t| q.get_type() == t))
+            .filter(|(_, q)| state.points_filter.is_none_or(|p| q.get_points() == p))
+            .filter(|(_, q)| q.matches_search(&state.search, state.fuzzy_search))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn draw_search(&mut self, ui: &mut Ui) {
+        let state = &mut self.gui_state;
+        ui.horizontal(|ui| {
+            egui::TextEdit::singleline(&mut state.search)
+                .hint_text("Search questions and answers")
+                .show(ui);
+            ui.checkbox(&mut state.fuzzy_search, "Fuzzy").on_hover_text(
+                "Also match the letters of the search in order, like 'nwtn' for 'Newton'",
+            );
+        });
+
+        let mut points: Vec<u8> = self.questions.iter().map(Question::get_points).collect();
+        points.sort();
+        points.dedup();
+        ui.horizontal(|ui| {
+            ui.label("Type");
+            ui.push_id("type_filter_combobox", |ui| {
+                egui::ComboBox::from_label("")
+                    .selected_text(state.type_filter.map_or("Any", |t| t.get_name()))
+                    .show_ui(ui, |ui| {
+                        ui.style_mut().wrap = Some(false);
+                        ui.set_min_width(60.0);
+                        ui.selectable_value(&mut state.type_filter, None, "Any");
+                        for t in QuestionType::ALL {
+                            ui.selectable_value(&mut state.type_filter, Some(t), t.get_name());
+                        }
+                    });
+            });
+            ui.label("Points");
+            ui.push_id("points_filter_combobox", |ui| {
+                egui::ComboBox::from_label("")
+                    .selected_text(state.points_filter.map_or("Any".into(), |p| p.to_string()))
+                    .show_ui(ui, |ui| {
+                        ui.style_mut().wrap = Some(false);
+                        ui.set_min_width(60.0);
+                        ui.selectable_value(&mut state.points_filter, None, "Any");
+                        for p in points {
+                            ui.selectable_value(&mut state.points_filter, Some(p), p.to_string());
+                        }
+                    });
+            });
+        });
+    }
+
+    /// Moves the selection to the first question the list shows when the filters hide it. This
+    /// runs before the frame's snapshot, so edits are recorded for the question they were made to.
+    fn keep_selection_visible(&mut self) {
+        let visible = self.visible_questions();
+        if !visible.is_empty() && !visible.contains(&self.gui_state.selected_question) {
+            self.gui_state.selected_question = visible[0];
+        }
+    }
+
     fn draw_questions(&mut self, ui: &mut Ui) {
+        let visible = self.visible_questions();
         let sel_idx = self.gui_state.selected_question;
-        if let Some(question) = self.questions.get_mut(sel_idx) {
+        let editing = visible.contains(&sel_idx);
+        if let Some(question) = self.questions.get_mut(sel_idx).filter(|_| editing) {
             add_label("Question Editor", ui);
             let question_title = question.get_title_buf();
-            let response = egui::TextEdit::singleline(question_title).show(ui).response;
+            let response = egui::TextEdit::multiline(question_title)
+                .desired_rows(1)
+                .show(ui)
+                .response
+                .on_hover_text(
+                    "**bold**, *italic*, __underline__, H~2~O, x^2^\n\
+                     Formulas like $\\frac{a}{b}$, $x^2$ or $\\sqrt{\\alpha}$, \
+                     $$formula$$ on a line of its own\n\
+                     Put code between lines with ``` to print it in a monospace font",
+                );
+            if let Err(err) = markup::validate(question_title) {
+                ui.colored_label(ui.visuals().error_fg_color, format!("{:#}", err));
+            }
             if response.lost_focus() {
                 question.update_title_from_buf();
             }
@@ -126,11 +664,12 @@ impl Project {
                         ui.label("Points");
                         ui.add(egui::Slider::new(&mut q.points, 1..=8));
                     });
+                    scoring_policy(&mut q.scoring, ui);
                     ui.collapsing("Correct answers", |ui| {
-                        add_answers(&mut self.gui_state, &mut q.correct, ui)
+                        add_selection_answers(&mut self.gui_state, &mut q.correct, ui)
                     });
                     ui.collapsing("Incorrect answers", |ui| {
-                        add_answers(&mut self.gui_state, &mut q.incorrect, ui)
+                        add_selection_answers(&mut self.gui_state, &mut q.incorrect, ui)
                     });
                 }
                 Question::Input(q) => {
@@ -144,31 +683,172 @@ impl Project {
                             .on_hover_text("How many lines of text to be generated");
                     });
                 }
+                Question::TrueFalse(q) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Points");
+                        ui.add(egui::Slider::new(&mut q.points, 1..=8));
+                    });
+                    ui.checkbox(&mut q.answer, "The statement is true");
+                }
+                Question::Matching(q) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Points");
+                        ui.add(egui::Slider::new(&mut q.points, 1..=8));
+                    });
+                    ui.collapsing("Pairs", |ui| add_pairs(&mut q.pairs, ui));
+                }
+                Question::Ordering(q) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Points");
+                        ui.add(egui::Slider::new(&mut q.points, 1..=8));
+                    });
+                    ui.collapsing("Items in correct order", |ui| add_items(&mut q.items, ui));
+                }
+                Question::Numeric(q) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Points      ");
+                        ui.add(egui::Slider::new(&mut q.points, 1..=8));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Answer      ");
+                        ui.add(egui::DragValue::new(&mut q.value).speed(0.1));
+                        egui::TextEdit::singleline(&mut q.unit)
+                            .hint_text("unit")
+                            .desired_width(60.0)
+                            .show(ui);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Tolerance  ");
+                        ui.add(
+                            egui::DragValue::new(&mut q.tolerance)
+                                .speed(0.01)
+                                .clamp_range(0.0..=f64::MAX),
+                        )
+                        .on_hover_text("Answers this close to the correct one are accepted");
+                    });
+                }
+                Question::Cloze(q) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Points");
+                        ui.add(egui::Slider::new(&mut q.points, 1..=8));
+                    });
+                    ui.label("Text, mark blanks as {{answer}}");
+                    egui::TextEdit::multiline(&mut q.text)
+                        .desired_rows(3)
+                        .show(ui);
+                    if let Err(err) = q.parts() {
+                        ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                    }
+                }
             }
 
+            let meta = question.meta_mut();
+            ui.horizontal(|ui| {
+                ui.label("Tags          ");
+                let response = egui::TextEdit::singleline(&mut meta.tags_buf)
+                    .hint_text("loops, pointers")
+                    .show(ui)
+                    .response;
+                if response.lost_focus() {
+                    meta.update_tags_from_buf();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Difficulty    ");
+                difficulty_combo("difficulty_combobox", &mut meta.difficulty, "None", ui);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Section       ");
+                ui.push_id("section_combobox", |ui| {
+                    let selected = if meta.section.is_empty() {
+                        "None"
+                    } else {
+                        meta.section.as_str()
+                    };
+                    egui::ComboBox::from_label("")
+                        .selected_text(selected.to_string())
+                        .show_ui(ui, |ui| {
+                            ui.style_mut().wrap = Some(false);
+                            ui.set_min_width(60.0);
+                            ui.selectable_value(&mut meta.section, String::new(), "None");
+                            for section in self.sections.iter() {
+                                ui.selectable_value(
+                                    &mut meta.section,
+                                    section.title.clone(),
+                                    &section.title,
+                                );
+                            }
+                        });
+                });
+            });
+            ui.push_id("question_image", |ui| {
+                edit_image(&mut self.gui_state, &mut meta.image, ui)
+            });
+            draw_stats(question, ui);
+
             ui.horizontal(|ui| {
                 if ui.button("Remove question").clicked() {
-                    self.questions.remove(self.gui_state.selected_question);
-                    self.gui_state.selected_question = sel_idx.checked_sub(1).unwrap_or(0);
+                    self.edit(Edit::RemoveQuestion {
+                        index: sel_idx,
+                        question: Box::new(self.questions[sel_idx].clone()),
+                    });
                 }
                 if ui.button("Clone question").clicked() {
-                    let question = &self.questions[self.gui_state.selected_question];
-                    self.questions.push(question.clone());
+                    self.edit(Edit::InsertQuestion {
+                        index: sel_idx + 1,
+                        question: Box::new(self.questions[sel_idx].clone()),
+                    });
+                }
+                if ui
+                    .add_enabled(sel_idx > 0, egui::Button::new("Move up"))
+                    .on_hover_text("Alt+Up")
+                    .clicked()
+                {
+                    self.move_selected_question(-1);
+                }
+                if ui
+                    .add_enabled(
+                        sel_idx + 1 < self.questions.len(),
+                        egui::Button::new("Move down"),
+                    )
+                    .on_hover_text("Alt+Down")
+                    .clicked()
+                {
+                    self.move_selected_question(1);
                 }
             });
         }
 
+        add_label("Sections", ui);
+        self.draw_sections(ui);
+
         add_label("Questions", ui);
 
         ui.horizontal(|ui| {
             if ui.button("Add Selection").clicked() {
-                self.questions.push(Question::Selection(
+                self.add_question(Question::Selection(
                     crate::data::SelectionQuestion::default(),
                 ));
             }
             if ui.button("Add Input").clicked() {
-                self.questions
-                    .push(Question::Input(crate::data::InputQuestion::default()));
+                self.add_question(Question::Input(crate::data::InputQuestion::default()));
+            }
+            if ui.button("Add True/False").clicked() {
+                self.add_question(Question::TrueFalse(
+                    crate::data::TrueFalseQuestion::default(),
+                ));
+            }
+            if ui.button("Add Matching").clicked() {
+                self.add_question(Question::Matching(crate::data::MatchingQuestion::default()));
+            }
+            if ui.button("Add Cloze").clicked() {
+                self.add_question(Question::Cloze(crate::data::ClozeQuestion::default()));
+            }
+            if ui.button("Add Ordering").clicked() {
+                self.add_question(Question::Ordering(crate::data::OrderingQuestion::default()));
+            }
+            if ui.button("Add Numeric").clicked() {
+                self.add_question(Question::Numeric(crate::data::NumericQuestion::default()));
             }
         });
         ui.add_space(4.0);
@@ -176,17 +856,91 @@ impl Project {
         if self.questions.is_empty() {
             ui.label("No questions added yet");
         } else {
+            self.draw_search(ui);
+            let visible = self.visible_questions();
+            if visible.len() != self.questions.len() {
+                ui.label(format!(
+                    "{} of {} questions match",
+                    visible.len(),
+                    self.questions.len()
+                ));
+            }
+
             ScrollArea::vertical().show(ui, |ui| {
-                for (idx, q) in self.questions.iter().enumerate() {
+                let mut rows = vec![];
+                for &idx in visible.iter() {
+                    let q = &self.questions[idx];
                     let selected = idx == sel_idx;
 
-                    let sel_label = ui.selectable_label(selected, q.get_title());
+                    let warnings = q.meta().stats.as_ref().map(ItemStats::warnings);
+                    let warnings = warnings.unwrap_or_default();
+                    let mut sel_label = if warnings.is_empty() {
+                        ui.selectable_label(selected, q.get_short_title())
+                    } else {
+                        ui.selectable_label(selected, format!("⚠ {}", q.get_short_title()))
+                    };
+                    sel_label = sel_label.interact(egui::Sense::drag());
+                    if !warnings.is_empty() {
+                        sel_label = sel_label.on_hover_text(warnings.join("\n"));
+                    }
                     if sel_label.clicked() {
                         self.gui_state.selected_question = idx;
                     }
+                    rows.push((sel_label.rect, sel_label));
                 }
+                // Rows are dragged within the filtered list, so map them back to the whole bank
+                if let Some((from, to)) = reorder(ui, ui.id().with("questions"), &rows) {
+                    self.edit(Edit::MoveQuestion {
+                        from: visible[from],
+                        to: visible[to],
+                    });
+                }
+            });
+        }
+    }
+    fn draw_sections(&mut self, ui: &mut Ui) {
+        let mut rename = None;
+        let mut remove = None;
+        for (i, section) in self.sections.iter_mut().enumerate() {
+            ui.push_id(("section", i), |ui| {
+                ui.collapsing(section.title.clone(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Title              ");
+                        let response = egui::TextEdit::singleline(&mut section.title_buf)
+                            .show(ui)
+                            .response;
+                        if response.lost_focus() {
+                            rename = Some(i);
+                        }
+                    });
+                    ui.label("Instructions");
+                    egui::TextEdit::multiline(&mut section.instructions)
+                        .desired_rows(2)
+                        .show(ui);
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut section.show_subtotal, "Show subtotal");
+                        ui.checkbox(&mut section.shuffle, "Shuffle questions");
+                    });
+                    if ui.button("Remove section").clicked() {
+                        remove = Some(i);
+                    }
+                });
             });
         }
+        if let Some(i) = rename {
+            let mut renamed = Ok(());
+            self.edit_sections(|project| renamed = project.rename_section(i));
+            if let Err(err) = renamed {
+                let mut toasts = self.gui_state.toasts.lock().unwrap();
+                toasts.error(format!("{:#}", err));
+            }
+        }
+        if let Some(i) = remove {
+            self.edit_sections(|project| project.remove_section(i));
+        }
+        if ui.button("Add section").clicked() {
+            self.edit_sections(Project::add_section);
+        }
     }
     fn draw_configuration(&mut self, ui: &mut Ui) {
         add_label("General settings", ui);
@@ -237,6 +991,26 @@ impl Project {
             egui::TextEdit::singleline(&mut self.settings.font).show(ui);
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Code font        ");
+            egui::TextEdit::singleline(&mut self.settings.code_font).show(ui);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Math font         ");
+            egui::TextEdit::singleline(&mut self.settings.math_font).show(ui);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Code line numbers ");
+            ui.checkbox(&mut self.settings.code_line_numbers, "");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Frame code blocks ");
+            ui.checkbox(&mut self.settings.code_frame, "");
+        });
+
         add_label("Questions settings", ui);
         ui.horizontal(|ui| {
             ui.label("Show number of correct answers ");
@@ -253,6 +1027,139 @@ impl Project {
             ui.add(egui::Slider::new(&mut self.settings.max_questions, 1..=100));
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Variants             ");
+            ui.add(egui::Slider::new(&mut self.settings.variants, 1..=26))
+                .on_hover_text("Number of groups (A, B, C, ...) to generate");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Answer sheet      ");
+            ui.checkbox(&mut self.settings.answer_sheet, "")
+                .on_hover_text("Append a bubble sheet for grading with a scanner");
+        });
+
+        if self.settings.answer_sheet {
+            ui.horizontal(|ui| {
+                ui.label("Student ID digits ");
+                ui.add(egui::Slider::new(
+                    &mut self.settings.student_id_digits,
+                    1..=12,
+                ));
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Fixed seed         ");
+            let mut fixed = self.settings.seed.is_some();
+            if ui.checkbox(&mut fixed, "").changed() {
+                self.settings.seed = fixed.then_some(0);
+            }
+            if let Some(seed) = &mut self.settings.seed {
+                ui.add(egui::DragValue::new(seed))
+                    .on_hover_text("Generating with the same seed gives the same papers");
+            }
+        });
+
+        add_label("Grading", ui);
+        ui.horizontal(|ui| {
+            ui.label("Grading scale    ");
+            ui.push_id("grading_scale_comboxbox", |ui| {
+                let scale = &mut self.settings.grading_scale;
+                egui::ComboBox::from_label("")
+                    .selected_text(scale.get_name())
+                    .show_ui(ui, |ui| {
+                        ui.style_mut().wrap = Some(false);
+                        ui.set_min_width(60.0);
+                        ui.selectable_value(scale, GradingScale::None, "None");
+                        ui.selectable_value(scale, GradingScale::Percentage, "Percentage");
+                        if ui
+                            .selectable_label(
+                                matches!(scale, GradingScale::Thresholds { .. }),
+                                "Thresholds",
+                            )
+                            .clicked()
+                            && !matches!(scale, GradingScale::Thresholds { .. })
+                        {
+                            *scale = GradingScale::bulgarian();
+                        }
+                    });
+            });
+        });
+
+        if matches!(self.settings.grading_scale, GradingScale::Thresholds { .. }) {
+            ui.horizontal(|ui| {
+                ui.label("Presets ");
+                if ui.button("Bulgarian 2-6").clicked() {
+                    self.settings.grading_scale = GradingScale::bulgarian();
+                }
+                if ui.button("Letters A-F").clicked() {
+                    self.settings.grading_scale = GradingScale::letters();
+                }
+            });
+        }
+
+        if let GradingScale::Thresholds { grades } = &mut self.settings.grading_scale {
+            let mut remove = None;
+            for (i, grade) in grades.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    egui::TextEdit::singleline(&mut grade.name)
+                        .hint_text("grade")
+                        .desired_width(60.0)
+                        .show(ui);
+                    ui.add(
+                        egui::DragValue::new(&mut grade.min_percent)
+                            .clamp_range(0.0..=100.0)
+                            .suffix("%"),
+                    )
+                    .on_hover_text("The lowest share of the points that earns this grade");
+                    if ui.button("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                grades.remove(i);
+            }
+            if ui.button("Add grade").clicked() {
+                grades.push(Grade {
+                    name: String::new(),
+                    min_percent: 0.0,
+                });
+            }
+        }
+
+        add_label("Blueprint", ui);
+        ui.label(
+            "Draw questions from the bank by tag and difficulty instead of taking the first ones",
+        );
+
+        let mut remove = None;
+        for (i, rule) in self.blueprint.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::Slider::new(&mut rule.count, 1..=50));
+                egui::TextEdit::singleline(&mut rule.tag)
+                    .hint_text("any tag")
+                    .desired_width(120.0)
+                    .show(ui);
+                difficulty_combo(
+                    &format!("blueprint_combobox_{i}"),
+                    &mut rule.difficulty,
+                    "Any",
+                    ui,
+                );
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.blueprint.remove(i);
+        }
+        if ui.button("Add rule").clicked() {
+            self.blueprint.push(BlueprintRule::default());
+        }
+
         add_label("Header settings", ui);
 
         ui.horizontal(|ui| {
@@ -274,7 +1181,44 @@ impl Project {
 }
 
 impl eframe::App for Project {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let title = self.window_title();
+        if title != self.gui_state.window_title {
+            frame.set_window_title(&title);
+            self.gui_state.window_title = title;
+        }
+
+        // Checked before drawing, so text fields don't get to undo their own edits
+        let (undo, redo, up, down) = ctx.input_mut(|input| {
+            (
+                input.consume_key(Modifiers::COMMAND, Key::Z),
+                input.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z),
+                input.consume_key(Modifiers::ALT, Key::ArrowUp),
+                input.consume_key(Modifiers::ALT, Key::ArrowDown),
+            )
+        });
+        if undo {
+            self.undo();
+        }
+        if redo {
+            self.redo();
+        }
+        if up {
+            self.move_selected_question(-1);
+        }
+        if down {
+            self.move_selected_question(1);
+        }
+        self.keep_selection_visible();
+        let snapshot = self.snapshot();
+
+        if self.gui_state.show_preview {
+            egui::SidePanel::right("preview")
+                .resizable(true)
+                .default_width(420.0)
+                .show(ctx, |ui| self.draw_preview(ui));
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             self.draw_topbar(ui);
 
@@ -288,5 +1232,15 @@ impl eframe::App for Project {
                 toasts.show(ctx);
             }
         });
+
+        self.record_changes(snapshot);
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(
+            storage,
+            RECENT_PROJECTS_KEY,
+            &self.gui_state.recent_projects,
+        );
     }
 }
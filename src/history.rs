@@ -0,0 +1,366 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    data::{BlueprintRule, Header, Project, Question, Section},
+    settings::Settings,
+};
+
+/// Edits to the same thing closer together than this, like typing a word, are undone together.
+const MERGE_WINDOW: Duration = Duration::from_millis(800);
+const MAX_UNDO_STEPS: usize = 200;
+
+/// A change to a project that can be undone.
+#[derive(Clone)]
+pub enum Edit {
+    InsertQuestion {
+        index: usize,
+        question: Box<Question>,
+    },
+    RemoveQuestion {
+        index: usize,
+        question: Box<Question>,
+    },
+    MoveQuestion {
+        from: usize,
+        to: usize,
+    },
+    ReplaceQuestion {
+        index: usize,
+        before: Box<Question>,
+        after: Box<Question>,
+    },
+    /// Changes to many questions at once, like moving them out of a removed section.
+    ReplaceQuestions {
+        before: Vec<Question>,
+        after: Vec<Question>,
+    },
+    ReplaceSettings {
+        before: Box<Settings>,
+        after: Box<Settings>,
+    },
+    ReplaceHeader {
+        before: Header,
+        after: Header,
+    },
+    ReplaceSections {
+        before: Vec<Section>,
+        after: Vec<Section>,
+    },
+    ReplaceBlueprint {
+        before: Vec<BlueprintRule>,
+        after: Vec<BlueprintRule>,
+    },
+    /// Edits undone and redone as one step, applied in order.
+    Batch(Vec<Edit>),
+}
+impl Edit {
+    /// The edit that takes a project back to where it was before this one.
+    fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::InsertQuestion { index, question } => Edit::RemoveQuestion { index, question },
+            Edit::RemoveQuestion { index, question } => Edit::InsertQuestion { index, question },
+            Edit::MoveQuestion { from, to } => Edit::MoveQuestion { from: to, to: from },
+            Edit::ReplaceQuestion {
+                index,
+                before,
+                after,
+            } => Edit::ReplaceQuestion {
+                index,
+                before: after,
+                after: before,
+            },
+            Edit::ReplaceQuestions { before, after } => Edit::ReplaceQuestions {
+                before: after,
+                after: before,
+            },
+            Edit::ReplaceSettings { before, after } => Edit::ReplaceSettings {
+                before: after,
+                after: before,
+            },
+            Edit::ReplaceHeader { before, after } => Edit::ReplaceHeader {
+                before: after,
+                after: before,
+            },
+            Edit::ReplaceSections { before, after } => Edit::ReplaceSections {
+                before: after,
+                after: before,
+            },
+            Edit::ReplaceBlueprint { before, after } => Edit::ReplaceBlueprint {
+                before: after,
+                after: before,
+            },
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+
+    fn apply(&self, project: &mut Project) {
+        let selected = &mut project.gui_state.selected_question;
+        match self {
+            Edit::InsertQuestion { index, question } => {
+                project.questions.insert(*index, *question.clone());
+                *selected = *index;
+            }
+            Edit::RemoveQuestion { index, .. } => {
+                project.questions.remove(*index);
+                *selected = index.saturating_sub(1);
+            }
+            Edit::MoveQuestion { from, to } => {
+                let question = project.questions.remove(*from);
+                project.questions.insert(*to, question);
+                *selected = *to;
+            }
+            Edit::ReplaceQuestion { index, after, .. } => {
+                project.questions[*index] = *after.clone();
+                *selected = *index;
+            }
+            Edit::ReplaceQuestions { after, .. } => project.questions = after.clone(),
+            Edit::ReplaceSettings { after, .. } => project.settings = *after.clone(),
+            Edit::ReplaceHeader { after, .. } => project.header = after.clone(),
+            Edit::ReplaceSections { after, .. } => project.sections = after.clone(),
+            Edit::ReplaceBlueprint { after, .. } => project.blueprint = after.clone(),
+            Edit::Batch(edits) => {
+                for edit in edits {
+                    edit.apply(project);
+                }
+            }
+        }
+    }
+
+    /// Folds `next` into this edit when both replace the same thing.
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::ReplaceQuestion { index, after, .. },
+                Edit::ReplaceQuestion {
+                    index: next_index,
+                    after: next_after,
+                    ..
+                },
+            ) if index == next_index => *after = next_after.clone(),
+            (Edit::ReplaceSettings { after, .. }, Edit::ReplaceSettings { after: next, .. }) => {
+                *after = next.clone()
+            }
+            (Edit::ReplaceHeader { after, .. }, Edit::ReplaceHeader { after: next, .. }) => {
+                *after = next.clone()
+            }
+            (Edit::ReplaceSections { after, .. }, Edit::ReplaceSections { after: next, .. }) => {
+                *after = next.clone()
+            }
+            (Edit::ReplaceBlueprint { after, .. }, Edit::ReplaceBlueprint { after: next, .. }) => {
+                *after = next.clone()
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    last_edit: Option<Instant>,
+    /// Counts every change to the history, so the editor can tell when a frame made one.
+    revision: u64,
+}
+impl History {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Takes over from the history of a project this one replaces. Its revision moves on, so the
+    /// frame that replaced the project doesn't record the difference to the old one as an edit.
+    pub fn replace(&mut self, old: &History) {
+        self.revision = old.revision + 1;
+    }
+
+    fn push(&mut self, edit: Edit) {
+        let now = Instant::now();
+        let recent = self
+            .last_edit
+            .is_some_and(|last| now.duration_since(last) < MERGE_WINDOW);
+        self.last_edit = Some(now);
+        self.revision += 1;
+        self.redo.clear();
+
+        if let Some(last) = self.undo.last_mut() {
+            if recent && last.merge(&edit) {
+                return;
+            }
+        }
+        self.undo.push(edit);
+        if self.undo.len() > MAX_UNDO_STEPS {
+            self.undo.remove(0);
+        }
+    }
+}
+
+/// The parts of a project the editor changes in place, taken before a frame is drawn to find
+/// out what the frame changed.
+pub struct Snapshot {
+    revision: u64,
+    index: usize,
+    question: Option<Question>,
+    questions: usize,
+    settings: Settings,
+    header: Header,
+    sections: Vec<Section>,
+    blueprint: Vec<BlueprintRule>,
+}
+
+impl Project {
+    /// Applies an edit and remembers it for undoing.
+    pub fn edit(&mut self, edit: Edit) {
+        edit.apply(self);
+        self.gui_state.history.push(edit);
+    }
+
+    /// Runs `change`, which can touch any question or section, as one edit.
+    pub fn edit_sections(&mut self, change: impl FnOnce(&mut Project)) {
+        let sections = self.sections.clone();
+        let questions = self.questions.clone();
+        change(self);
+
+        let mut edits = vec![];
+        if self.sections != sections {
+            edits.push(Edit::ReplaceSections {
+                before: sections,
+                after: self.sections.clone(),
+            });
+        }
+        if self.questions != questions {
+            edits.push(Edit::ReplaceQuestions {
+                before: questions,
+                after: self.questions.clone(),
+            });
+        }
+        if !edits.is_empty() {
+            self.gui_state.history.push(Edit::Batch(edits));
+        }
+    }
+
+    pub fn undo(&mut self) {
+        let history = &mut self.gui_state.history;
+        let Some(edit) = history.undo.pop() else {
+            return;
+        };
+        history.revision += 1;
+        history.last_edit = None;
+        history.redo.push(edit.clone());
+        edit.inverse().apply(self);
+    }
+
+    pub fn redo(&mut self) {
+        let history = &mut self.gui_state.history;
+        let Some(edit) = history.redo.pop() else {
+            return;
+        };
+        history.revision += 1;
+        history.last_edit = None;
+        history.undo.push(edit.clone());
+        edit.apply(self);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let index = self.gui_state.selected_question;
+        Snapshot {
+            revision: self.gui_state.history.revision,
+            index,
+            question: self.questions.get(index).cloned(),
+            questions: self.questions.len(),
+            settings: self.settings.clone(),
+            header: self.header.clone(),
+            sections: self.sections.clone(),
+            blueprint: self.blueprint.clone(),
+        }
+    }
+
+    /// Remembers the changes made in place since `snapshot` was taken, like typing into a text
+    /// field, for undoing. Frames that already made an edit of their own are left alone.
+    pub fn record_changes(&mut self, snapshot: Snapshot) {
+        let history = &mut self.gui_state.history;
+        if history.revision != snapshot.revision || self.questions.len() != snapshot.questions {
+            return;
+        }
+
+        if let Some(before) = snapshot.question {
+            let after = &self.questions[snapshot.index];
+            if *after != before {
+                history.push(Edit::ReplaceQuestion {
+                    index: snapshot.index,
+                    before: Box::new(before),
+                    after: Box::new(after.clone()),
+                });
+            }
+        }
+        if self.settings != snapshot.settings {
+            history.push(Edit::ReplaceSettings {
+                before: Box::new(snapshot.settings),
+                after: Box::new(self.settings.clone()),
+            });
+        }
+        if self.header != snapshot.header {
+            history.push(Edit::ReplaceHeader {
+                before: snapshot.header,
+                after: self.header.clone(),
+            });
+        }
+        if self.sections != snapshot.sections {
+            history.push(Edit::ReplaceSections {
+                before: snapshot.sections,
+                after: self.sections.clone(),
+            });
+        }
+        if self.blueprint != snapshot.blueprint {
+            history.push(Edit::ReplaceBlueprint {
+                before: snapshot.blueprint,
+                after: self.blueprint.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titled(title: &str) -> Project {
+        Project {
+            header: Header {
+                title: title.into(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_changes_in_place() {
+        let mut project = titled("Before");
+        let snapshot = project.snapshot();
+        project.header.title = "After".into();
+        project.record_changes(snapshot);
+        assert!(project.gui_state.history.can_undo());
+
+        project.undo();
+        assert_eq!(project.header.title, "Before");
+    }
+
+    #[test]
+    fn replacing_the_project_is_not_an_edit() {
+        let mut project = titled("Old");
+        let snapshot = project.snapshot();
+
+        let mut opened = titled("Opened");
+        opened.gui_state.history.replace(&project.gui_state.history);
+        project = opened;
+        project.record_changes(snapshot);
+        assert!(!project.gui_state.history.can_undo());
+        assert_eq!(project.header.title, "Opened");
+    }
+}
@@ -0,0 +1,729 @@
+//! What a paper and its answer key are made of: their elements with the text, sizes and spacing
+//! of each. `pdf_gen` prints these elements and `preview` draws them in the editor, so the two
+//! only differ in how they measure text.
+
+use ilog::IntLog;
+
+use crate::{
+    data::{
+        ClozePart, ClozeQuestion, Project, Question, QuestionImage, ScoringPolicy,
+        SelectionQuestion,
+    },
+    markup,
+    paper::{Paper, PaperAnswer, PaperPart},
+    pdf_elements::AlphabeticOrderedList,
+    settings::{GradingScale, Language},
+};
+
+/// The size of regular text in points.
+pub const FONT_SIZE: u8 = 12;
+/// The margins of the page in millimeters.
+pub const MARGIN: f64 = 10.0;
+/// How far the text after the first line of a question and its answers are moved right.
+const INDENT: f64 = 5.0;
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy)]
+pub struct Style {
+    /// In points.
+    pub size: u8,
+    pub bold: bool,
+    pub italic: bool,
+    pub grey: bool,
+}
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            size: FONT_SIZE,
+            bold: false,
+            italic: false,
+            grey: false,
+        }
+    }
+}
+impl Style {
+    pub fn sized(size: u8) -> Self {
+        Self {
+            size,
+            ..Default::default()
+        }
+    }
+    pub fn bold(self) -> Self {
+        Self { bold: true, ..self }
+    }
+    pub fn italic(self) -> Self {
+        Self {
+            italic: true,
+            ..self
+        }
+    }
+    pub fn grey(self) -> Self {
+        Self { grey: true, ..self }
+    }
+}
+
+/// A run of text in a single style. With `markup` set its inline markup is applied.
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+    pub markup: bool,
+}
+
+/// Text that wraps to the width it gets.
+#[derive(Default)]
+pub struct Text {
+    pub spans: Vec<Span>,
+    pub align: Align,
+}
+impl Text {
+    pub fn new(text: impl Into<String>, style: Style) -> Self {
+        Self::default().and(text, style)
+    }
+    fn markup(line: impl Into<String>, style: Style) -> Self {
+        Self::default().and_markup(line, style)
+    }
+    fn and(mut self, text: impl Into<String>, style: Style) -> Self {
+        self.spans.push(Span {
+            text: text.into(),
+            style,
+            markup: false,
+        });
+        self
+    }
+    fn and_markup(mut self, line: impl Into<String>, style: Style) -> Self {
+        self.spans.push(Span {
+            text: line.into(),
+            style,
+            markup: true,
+        });
+        self
+    }
+    fn aligned(self, align: Align) -> Self {
+        Self { align, ..self }
+    }
+
+    /// Whether none of the text has inline markup.
+    pub fn is_plain(&self) -> bool {
+        self.spans.iter().all(|span| !span.markup)
+    }
+}
+
+/// Space around an element in millimeters.
+#[derive(Clone, Copy, Default)]
+pub struct Margins {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+impl Margins {
+    fn trbl(top: f64, right: f64, bottom: f64, left: f64) -> Self {
+        Self {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+    fn vh(vertical: f64, horizontal: f64) -> Self {
+        Self::trbl(vertical, horizontal, vertical, horizontal)
+    }
+    fn left(left: f64) -> Self {
+        Self::trbl(0.0, 0.0, 0.0, left)
+    }
+}
+
+pub enum Element {
+    Text(Text),
+    /// Question or answer text: lines with inline markup, formulas on lines of their own and
+    /// code blocks.
+    Blocks(String, Style),
+    Image(QuestionImage),
+    /// `left` gets the given share of the width and `right` the rest. With a share of 0 `left`
+    /// only takes the width it needs.
+    Split(Box<Element>, Box<Element>, f64),
+    /// Elements below each other.
+    Column(Vec<Element>),
+    Padded(Box<Element>, Margins),
+    /// Items lettered from the given character, like `a)`.
+    List(char, Vec<Element>),
+    /// Empty space as high as this many lines of text.
+    Break(f64),
+    PageBreak,
+    /// A dotted line across the width to write on.
+    WritingLine,
+    /// An empty box, one line high and this many millimeters wide, to write a short answer in.
+    AnswerBox(f64),
+    /// Boxes to tick, each followed by its label.
+    TickBoxes(Vec<String>),
+    /// A table of the points needed for each grade. Every column holds a grade and its points,
+    /// the first one names the rows.
+    GradingTable(Vec<[String; 2]>),
+    /// Squares this many millimeters wide in the corners of the page for scanners.
+    RegistrationMarks(f64),
+    /// Bubbles for the digits of the student's ID.
+    IdBubbles(u8),
+    /// A row of answer bubbles for every question, as its number and how many answers it has,
+    /// lettered from the given character.
+    BubbleGrid(Vec<(usize, usize)>, char),
+}
+impl From<Text> for Element {
+    fn from(text: Text) -> Self {
+        Element::Text(text)
+    }
+}
+
+fn split(left: impl Into<Element>, right: impl Into<Element>, share: f64) -> Element {
+    Element::Split(Box::new(left.into()), Box::new(right.into()), share)
+}
+
+fn padded(element: impl Into<Element>, margins: Margins) -> Element {
+    Element::Padded(Box::new(element.into()), margins)
+}
+
+fn header(project: &Project, paper: &Paper, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+
+    elements.push(
+        Text::new(&project.header.title, Style::sized(18))
+            .aligned(Align::Center)
+            .into(),
+    );
+    if let Some(variant) = paper.variant {
+        let group = format!("{} {}", language.group(), variant);
+        elements.push(
+            Text::new(group, Style::sized(14).bold())
+                .aligned(Align::Center)
+                .into(),
+        );
+    }
+    elements.push(Element::Break(1.0));
+
+    let name = format!(
+        "{}: ________________________________________",
+        language.input_name()
+    );
+    let class = split(
+        Text::new(
+            format!("{}: _____", language.input_class()),
+            Style::sized(14),
+        ),
+        Text::new(
+            format!("{}: _____", language.input_class_num()),
+            Style::sized(14),
+        ),
+        0.5,
+    );
+    elements.push(split(Text::new(name, Style::sized(14)), class, 0.7));
+    elements.push(Element::Break(0.5));
+}
+
+fn section_header(project: &Project, paper: &Paper, part: &PaperPart, elements: &mut Vec<Element>) {
+    let Some(section) = &part.section else {
+        return;
+    };
+
+    let style = Style::sized(14).bold();
+    let title = Text::new(&section.title, style);
+    if section.show_subtotal {
+        let points = project
+            .settings
+            .language
+            .format_points(paper.points(part.questions.clone()));
+        elements.push(split(
+            title,
+            Text::new(points, style).aligned(Align::Right),
+            0.8,
+        ));
+    } else {
+        elements.push(title.into());
+    }
+
+    if !section.instructions.is_empty() {
+        elements.push(Text::new(&section.instructions, Style::default().italic()).into());
+    }
+    elements.push(Element::Break(0.5));
+}
+
+/// The numbered first line of the question with its points, followed by the rest of its text
+/// and its image.
+fn question_text(i: usize, question: &Question, project: &Project, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+    let show_hint = match question {
+        Question::Selection(q) => q.correct.len() >= 2 && project.settings.show_hints,
+        _ => false,
+    };
+
+    // The first line goes next to the number, the rest below it. Text with mistakes, which
+    // `Project::validate` keeps from being printed, is previewed as written.
+    let title = question.get_title();
+    let (first_line, rest) = match markup::split_first_line(&title) {
+        Some((line, rest)) => (line.to_string(), rest.to_string()),
+        None => (String::new(), title.clone()),
+    };
+
+    let mut text = Text::new(format!("{}. ", i + 1), Style::default())
+        .and_markup(first_line, Style::default());
+    if show_hint {
+        let hint = format!(" ({})", language.multiple_answers_hint());
+        text = text.and(hint, Style::default());
+    }
+    let points = language.format_points(question.get_points().into());
+    elements.push(Element::Column(vec![
+        split(
+            text,
+            Text::new(points, Style::default()).aligned(Align::Right),
+            0.9,
+        ),
+        padded(
+            Element::Blocks(rest, Style::default()),
+            Margins::left(INDENT),
+        ),
+    ]));
+
+    if let Some(image) = &question.meta().image {
+        elements.push(padded(
+            Element::Image(image.clone()),
+            Margins::trbl(1.0, 0.0, 1.0, INDENT),
+        ));
+    }
+}
+
+/// An answer's text and code blocks with its image below them.
+fn answer(answer: &PaperAnswer, style: Style) -> Element {
+    let mut column = vec![];
+    if !answer.text.is_empty() || answer.image.is_none() {
+        column.push(Element::Blocks(answer.text.clone(), style));
+    }
+    if let Some(image) = &answer.image {
+        column.push(padded(
+            Element::Image(image.clone()),
+            Margins::trbl(1.0, 0.0, 1.0, 0.0),
+        ));
+    }
+    Element::Column(column)
+}
+
+/// A blank about twice as wide as the hidden answer, so there is room to write it by hand. Its
+/// width does hint at how long the answer is.
+fn cloze_blank(answer: &str) -> String {
+    "_".repeat((answer.chars().count() * 2).max(6))
+}
+
+/// The text of a cloze question with every blank replaced by the text and style `blank` gives
+/// for its answer.
+fn cloze(question: &ClozeQuestion, blank: impl Fn(&str) -> (String, Style)) -> Text {
+    let Ok(parts) = question.parts() else {
+        return Text::new(&question.text, Style::default());
+    };
+    let mut text = Text::default();
+    for part in parts {
+        text = match part {
+            ClozePart::Text(t) => text.and(t, Style::default()),
+            ClozePart::Blank(answer) => {
+                let (blank, style) = blank(answer);
+                text.and(blank, style)
+            }
+        };
+    }
+    text
+}
+
+fn questions(project: &Project, paper: &Paper, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+    let first_char = language.get_first_char();
+
+    for part in paper.parts.iter() {
+        section_header(project, paper, part, elements);
+
+        for i in part.questions.clone() {
+            let paper_question = &paper.questions[i];
+            let question = &paper_question.question;
+            question_text(i, question, project, elements);
+
+            match question {
+                Question::Selection(_) => {
+                    let answers = paper_question.answers.iter();
+                    let items = answers.map(|a| answer(a, Style::default())).collect();
+                    elements.push(Element::List(first_char, items));
+                }
+                Question::Input(question) => {
+                    elements.push(Element::Break(0.5));
+                    for _ in 0..question.number_of_lines {
+                        elements.push(padded(Element::WritingLine, Margins::vh(1.5, 0.0)));
+                    }
+                }
+                Question::Matching(question) => {
+                    let terms = question.pairs.iter().enumerate().map(|(n, pair)| {
+                        Text::new(format!("{}. ", n + 1), Style::default())
+                            .and_markup(&pair.term, Style::default())
+                            .and(" ____", Style::default())
+                            .into()
+                    });
+                    let definitions = paper_question
+                        .answers
+                        .iter()
+                        .map(|answer| Text::markup(&answer.text, Style::default()).into());
+                    elements.push(padded(
+                        split(
+                            Element::Column(terms.collect()),
+                            Element::List(first_char, definitions.collect()),
+                            0.5,
+                        ),
+                        Margins::left(INDENT),
+                    ));
+                }
+                Question::Numeric(question) => {
+                    let answer = split(
+                        Text::new(format!("{}: ", language.answer()), Style::default()),
+                        split(
+                            Element::AnswerBox(30.0),
+                            Text::new(&question.unit, Style::default()),
+                            0.0,
+                        ),
+                        0.0,
+                    );
+                    elements.push(padded(answer, Margins::trbl(1.0, 0.0, 0.0, INDENT)));
+                }
+                Question::Ordering(_) => {
+                    let items = paper_question.answers.iter().map(|answer| {
+                        split(
+                            Element::AnswerBox(8.0),
+                            Text::markup(&answer.text, Style::default()),
+                            0.0,
+                        )
+                    });
+                    elements.push(Element::List(first_char, items.collect()));
+                }
+                Question::Cloze(question) => {
+                    let text = cloze(question, |answer| (cloze_blank(answer), Style::default()));
+                    elements.push(padded(text, Margins::trbl(1.0, 0.0, 0.0, INDENT)));
+                }
+                Question::TrueFalse(_) => {
+                    let labels = vec![
+                        language.true_name().to_string(),
+                        language.false_name().to_string(),
+                    ];
+                    elements.push(padded(
+                        Element::TickBoxes(labels),
+                        Margins::trbl(1.0, 0.0, 0.0, INDENT),
+                    ));
+                }
+            }
+
+            elements.push(Element::Break(1.0));
+        }
+    }
+}
+
+/// A small table of the points needed for each grade, `None` when the scale has no thresholds.
+fn grading_table(project: &Project, max_points: usize) -> Option<Element> {
+    let ranges = project.settings.grading_scale.point_ranges(max_points);
+    if ranges.is_empty() {
+        return None;
+    }
+
+    let language = &project.settings.language;
+    let mut columns = vec![[
+        language.grade().to_string(),
+        language.get_points_sum().to_string(),
+    ]];
+    columns.extend(ranges.into_iter().map(|(grade, range)| [grade, range]));
+    Some(Element::GradingTable(columns))
+}
+
+fn footer(project: &Project, max_points: usize, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+    let points_needed_space = max_points.log10() + 2;
+
+    let examiner = split(
+        Text::new(format!("{}: ", language.get_examiner()), Style::default()),
+        Text::new("______________________________", Style::default()),
+        0.0,
+    );
+    let points = format!(
+        "{}: {}/{}",
+        language.get_points_sum(),
+        "_".repeat(points_needed_space),
+        max_points
+    );
+    elements.push(split(examiner, Text::new(points, Style::default()), 0.7));
+
+    if project.settings.grading_scale != GradingScale::None {
+        elements.push(Element::Break(0.5));
+        let grade = Text::new(
+            format!("{}: __________", language.grade()),
+            Style::default(),
+        )
+        .aligned(Align::Right);
+        match grading_table(project, max_points) {
+            Some(table) => elements.push(split(table, grade, 0.7)),
+            None => elements.push(grade.into()),
+        }
+    }
+}
+
+fn seed_text(language: &Language, seed: Option<u64>) -> String {
+    match seed {
+        Some(seed) => format!("{}: {}", language.seed(), seed),
+        None => format!("{}: {}", language.seed(), language.random_seed()),
+    }
+}
+
+fn seed(project: &Project, seed: Option<u64>, elements: &mut Vec<Element>) {
+    let text = seed_text(&project.settings.language, seed);
+    elements.push(Element::Break(0.5));
+    elements.push(
+        Text::new(text, Style::sized(8).grey())
+            .aligned(Align::Right)
+            .into(),
+    );
+}
+
+/// A page with bubbles for the answers of the selection questions, for grading with a scanner.
+fn answer_sheet(project: &Project, paper: &Paper, seed: Option<u64>, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+
+    elements.push(Element::PageBreak);
+    elements.push(Element::RegistrationMarks(5.0));
+
+    let title = match paper.variant {
+        Some(variant) => format!(
+            "{} - {} - {} {}",
+            project.header.title,
+            language.answer_sheet(),
+            language.group(),
+            variant
+        ),
+        None => format!("{} - {}", project.header.title, language.answer_sheet()),
+    };
+    let name = format!(
+        "{}: ________________________________________",
+        language.input_name()
+    );
+    let rows = paper
+        .questions
+        .iter()
+        .enumerate()
+        .filter(|(_, q)| matches!(q.question, Question::Selection(_)))
+        .map(|(i, q)| (i + 1, q.answers.len()))
+        .collect();
+
+    let sheet = vec![
+        Text::new(title, Style::sized(16))
+            .aligned(Align::Center)
+            .into(),
+        Text::new(seed_text(language, seed), Style::default())
+            .aligned(Align::Center)
+            .into(),
+        Element::Break(1.0),
+        Text::new(name, Style::default()).into(),
+        Element::Break(0.5),
+        Text::new(language.student_id(), Style::default().bold()).into(),
+        padded(
+            Element::IdBubbles(project.settings.student_id_digits),
+            Margins::trbl(1.0, 0.0, 0.0, INDENT),
+        ),
+        Element::Break(1.0),
+        Text::new(
+            language.answer_sheet_instructions(),
+            Style::default().italic(),
+        )
+        .into(),
+        Element::Break(0.5),
+        Element::BubbleGrid(rows, language.get_first_char()),
+    ];
+    elements.push(padded(Element::Column(sheet), Margins::vh(8.0, 8.0)));
+}
+
+/// The elements of the paper a student gets. `seed` is the one printed on it, `None` when it is
+/// only chosen when the paper is generated.
+pub fn paper(project: &Project, paper: &Paper, seed: Option<u64>) -> Vec<Element> {
+    let mut elements = vec![];
+    header(project, paper, &mut elements);
+    questions(project, paper, &mut elements);
+    footer(project, paper.max_points(), &mut elements);
+    self::seed(project, seed, &mut elements);
+    if project.settings.answer_sheet {
+        answer_sheet(project, paper, seed, &mut elements);
+    }
+    elements
+}
+
+/// Describes how a selection question is scored, like "Scoring: 0.5 per correct answer".
+fn scoring_note(question: &SelectionQuestion, language: &Language) -> String {
+    // Round to hundredths so shares like 1/3 don't print every digit
+    let round = |points: f64| (points * 100.0).round() / 100.0;
+    let per_correct = f64::from(question.points) / question.correct.len().max(1) as f64;
+
+    let rule = match question.scoring {
+        ScoringPolicy::AllOrNothing => language.all_or_nothing().to_string(),
+        ScoringPolicy::Proportional => {
+            format!("{} {}", round(per_correct), language.per_correct_answer())
+        }
+        ScoringPolicy::Penalty {
+            penalty,
+            floor_at_zero,
+        } => {
+            let mut rule = format!(
+                "{} {}, -{} {}",
+                round(per_correct),
+                language.per_correct_answer(),
+                round(penalty),
+                language.per_wrong_answer()
+            );
+            if floor_at_zero {
+                rule = format!("{}, {}", rule, language.at_least_zero());
+            }
+            rule
+        }
+    };
+    format!("{}: {}", language.scoring(), rule)
+}
+
+fn key_header(project: &Project, paper: &Paper, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+
+    let title = match paper.variant {
+        Some(variant) => format!(
+            "{} - {} {} - {}",
+            project.header.title,
+            language.group(),
+            variant,
+            language.answer_key()
+        ),
+        None => format!("{} - {}", project.header.title, language.answer_key()),
+    };
+    elements.push(
+        Text::new(title, Style::sized(18))
+            .aligned(Align::Center)
+            .into(),
+    );
+    elements.push(Element::Break(1.0));
+}
+
+fn key_questions(project: &Project, paper: &Paper, elements: &mut Vec<Element>) {
+    let language = &project.settings.language;
+    let first_char = language.get_first_char();
+    let letter = |pos: usize| AlphabeticOrderedList::letter(first_char, pos as u32);
+    let correct = |answer: String| {
+        let text = format!("{}: {}", language.correct_answers(), answer);
+        Text::new(text, Style::default().bold()).into()
+    };
+
+    for part in paper.parts.iter() {
+        section_header(project, paper, part, elements);
+
+        for i in part.questions.clone() {
+            let paper_question = &paper.questions[i];
+            let question = &paper_question.question;
+            question_text(i, question, project, elements);
+
+            match question {
+                Question::Selection(selection) => {
+                    let mut items = vec![];
+                    let mut letters = vec![];
+                    for (pos, a) in paper_question.answers.iter().enumerate() {
+                        if a.correct {
+                            letters.push(letter(pos).to_string());
+                            items.push(answer(a, Style::default().bold()));
+                        } else {
+                            items.push(answer(a, Style::default()));
+                        }
+                    }
+                    elements.push(Element::List(first_char, items));
+                    // With a single correct answer all or nothing goes without saying
+                    let note = !(selection.scoring.is_all_or_nothing() && letters.len() == 1);
+                    elements.push(correct(letters.join(", ")));
+                    if note {
+                        let note = scoring_note(selection, language);
+                        elements.push(Text::new(note, Style::default().italic()).into());
+                    }
+                }
+                Question::Input(_) => {
+                    let text = Text::new(language.open_answer(), Style::default().italic());
+                    elements.push(text.into());
+                }
+                Question::Matching(question) => {
+                    let terms = question.pairs.iter().enumerate().map(|(n, pair)| {
+                        let mut term = Text::new(format!("{}. ", n + 1), Style::default())
+                            .and_markup(&pair.term, Style::default());
+                        if let Some(pos) = paper_question.position_of(n) {
+                            term = term.and(format!(" {}", letter(pos)), Style::default().bold());
+                        }
+                        term.into()
+                    });
+                    let definitions = paper_question
+                        .answers
+                        .iter()
+                        .map(|answer| Text::markup(&answer.text, Style::default()).into());
+                    elements.push(padded(
+                        split(
+                            Element::Column(terms.collect()),
+                            Element::List(first_char, definitions.collect()),
+                            0.5,
+                        ),
+                        Margins::left(INDENT),
+                    ));
+                }
+                Question::Numeric(question) => {
+                    elements.push(correct(question.format_answer()));
+                }
+                Question::Ordering(_) => {
+                    let items = paper_question.answers.iter().map(|answer| {
+                        Text::new(format!("{}. ", answer.source + 1), Style::default().bold())
+                            .and_markup(&answer.text, Style::default())
+                            .into()
+                    });
+                    elements.push(Element::List(first_char, items.collect()));
+
+                    let sequence: Vec<String> = (0..paper_question.answers.len())
+                        .filter_map(|source| paper_question.position_of(source))
+                        .map(|pos| letter(pos).to_string())
+                        .collect();
+                    elements.push(correct(sequence.join(", ")));
+                }
+                Question::Cloze(question) => {
+                    let text = cloze(question, |answer| (answer.into(), Style::default().bold()));
+                    elements.push(padded(text, Margins::trbl(1.0, 0.0, 0.0, INDENT)));
+                }
+                Question::TrueFalse(question) => {
+                    let answer = if question.answer {
+                        language.true_name()
+                    } else {
+                        language.false_name()
+                    };
+                    elements.push(correct(answer.to_string()));
+                }
+            }
+
+            elements.push(Element::Break(1.0));
+        }
+    }
+}
+
+/// The elements of the answer key of a paper.
+pub fn key(project: &Project, paper: &Paper) -> Vec<Element> {
+    let language = &project.settings.language;
+    let max_points = paper.max_points();
+
+    let mut elements = vec![];
+    key_header(project, paper, &mut elements);
+    key_questions(project, paper, &mut elements);
+
+    let points = format!("{}: {}", language.get_points_sum(), max_points);
+    elements.push(Text::new(points, Style::default()).into());
+    if let Some(table) = grading_table(project, max_points) {
+        elements.push(Element::Break(0.5));
+        elements.push(split(table, Text::default(), 0.7));
+    }
+    seed(project, Some(paper.seed), &mut elements);
+    elements
+}
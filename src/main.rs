@@ -1,38 +1,213 @@
 // Hide the console on Windows
 #![windows_subsystem = "windows"]
 
-use std::fs;
+use std::collections::BTreeSet;
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
 use data::Project;
 
+pub mod analysis;
 pub mod data;
 pub mod gui;
+pub mod history;
+pub mod layout;
+mod markup;
+mod math;
+pub mod paper;
 pub mod pdf_elements;
 pub mod pdf_gen;
 pub mod perf_test;
+pub mod preview;
+pub mod scoring;
 pub mod settings;
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     path: Option<String>,
     #[arg(long, default_value_t = false)]
     perf_test: bool,
+    /// Seed used for shuffling, overrides the one in the project file
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render a project to PDF without opening the editor
+    Generate {
+        path: String,
+        /// Output file, overrides the one in the project file
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Number of variants, overrides the one in the project file
+        #[arg(long)]
+        variants: Option<u8>,
+        /// Seed used for shuffling, overrides the one in the project file
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Score a CSV of student responses against the papers they were given
+    Score {
+        path: String,
+        /// CSV with the columns name, paper and one column per question number
+        responses: String,
+        /// Write the scores of every question to this CSV file
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Run an item analysis over a CSV of student responses
+    Analyze {
+        path: String,
+        /// CSV with the columns name, paper and one column per question number
+        responses: String,
+        /// Store the statistics in the project file, so the editor can flag weak questions
+        #[arg(long)]
+        save: bool,
+    },
+}
+
+fn generate(
+    path: &str,
+    output: Option<String>,
+    variants: Option<u8>,
+    seed: Option<u64>,
+) -> anyhow::Result<()> {
+    let mut project = Project::load(path)?;
+    if let Some(output) = output {
+        project.settings.output = output;
+    }
+    if let Some(variants) = variants {
+        project.settings.variants = variants;
+    }
+    if seed.is_some() {
+        project.settings.seed = seed;
+    }
+
+    let (paths, dur) = pdf_gen::generate_pdf(&project)?;
+    for path in paths {
+        println!("Generated {}", path.display());
+    }
+    println!("Done in {:.3}sec", dur.as_secs_f64());
+    Ok(())
+}
+
+fn score_responses(
+    project: &Project,
+    responses: &str,
+) -> anyhow::Result<Vec<scoring::StudentScore>> {
+    let responses = scoring::read_responses(responses)?;
+    responses
+        .iter()
+        .map(|response| {
+            scoring::score(project, response)
+                .with_context(|| format!("Could not score {}", response.name))
+        })
+        .collect()
+}
+
+/// Tells which answered questions were left out of the scores because they need a teacher.
+fn warn_unscored(scores: &[scoring::StudentScore]) {
+    let unscored: BTreeSet<usize> = scores
+        .iter()
+        .flat_map(|s| s.unscored.iter().copied())
+        .collect();
+    if !unscored.is_empty() {
+        let numbers: Vec<String> = unscored.iter().map(|n| n.to_string()).collect();
+        eprintln!(
+            "Question {} can't be scored automatically and was left out of the totals",
+            numbers.join(", ")
+        );
+    }
+}
+
+fn score(path: &str, responses: &str, output: Option<String>) -> anyhow::Result<()> {
+    let project = Project::load(path)?;
+    let scores = score_responses(&project, responses)?;
+    warn_unscored(&scores);
+    for score in scores.iter() {
+        let grade = match &score.grade {
+            Some(grade) => format!(", {}: {}", project.settings.language.grade(), grade),
+            None => String::new(),
+        };
+        println!(
+            "{} ({}): {}/{}{}",
+            score.name, score.paper, score.points, score.max_points, grade
+        );
+    }
+
+    if let Some(output) = output {
+        scoring::write_scores(&output, &scores)?;
+        println!("Wrote scores to {}", output);
+    }
+    Ok(())
+}
+
+fn analyze(path: &str, responses: &str, save: bool) -> anyhow::Result<()> {
+    let mut project = Project::load(path)?;
+    let scores = score_responses(&project, responses)?;
+    warn_unscored(&scores);
+
+    for (index, stats) in analysis::analyze(&project, &scores) {
+        let question = &mut project.questions[index];
+        let warnings = stats.warnings();
+        println!(
+            "{}. {}: difficulty {:.2}, discrimination {:.2}, distractors {:?}{}",
+            index + 1,
+            question.get_short_title(),
+            stats.difficulty,
+            stats.discrimination,
+            stats.distractors,
+            if warnings.is_empty() {
+                String::new()
+            } else {
+                format!(" - {}", warnings.join(", "))
+            }
+        );
+        question.meta_mut().stats = Some(stats);
+    }
+
+    if save {
+        project.save(path)?;
+        println!("Saved the statistics to {}", path);
+    }
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    if let Some(command) = args.command {
+        return match command {
+            Command::Generate {
+                path,
+                output,
+                variants,
+                seed,
+            } => generate(&path, output, variants, seed),
+            Command::Score {
+                path,
+                responses,
+                output,
+            } => score(&path, &responses, output),
+            Command::Analyze {
+                path,
+                responses,
+                save,
+            } => analyze(&path, &responses, save),
+        };
+    }
+
     let mut state = match &args.path {
-        Some(path) => {
-            let content = fs::read_to_string(path).unwrap();
-            toml::from_str(&content)?
-        }
+        Some(path) => Project::load(path)?,
         None => Project::default(),
     };
 
-    for q in state.questions.iter_mut() {
-        q.update_buf_from_title();
+    if args.seed.is_some() {
+        state.settings.seed = args.seed;
     }
 
     if args.perf_test {
@@ -42,6 +217,8 @@ fn main() -> anyhow::Result<()> {
         };
     }
 
+    state.gui_state.path = args.path.map(Into::into);
+
     println!("Starting egui");
     gui::run_gui(state);
     Ok(())
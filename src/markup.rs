@@ -0,0 +1,500 @@
+use anyhow::Context;
+
+use crate::math::{self, MathNode};
+
+/// The fence that opens and closes a code block. Anything after the opening fence, like the name
+/// of the language, is ignored.
+const CODE_FENCE: &str = "```";
+
+/// A piece of question or answer text.
+pub enum Block<'a> {
+    /// A line of regular text.
+    Text(&'a str),
+    /// The lines of a fenced code block, without the fences and with indentation preserved.
+    Code(Vec<&'a str>),
+    /// A line holding nothing but a `$$formula$$`, printed on its own.
+    Math(MathNode),
+}
+
+pub fn has_code(text: &str) -> bool {
+    text.lines().any(is_fence)
+}
+
+pub fn has_math(text: &str) -> bool {
+    parse_blocks(text).is_ok_and(|blocks| {
+        blocks.iter().any(|block| match block {
+            Block::Text(line) => tokenize(line)
+                .iter()
+                .any(|token| matches!(token, Token::Math(_))),
+            Block::Code(_) => false,
+            Block::Math(_) => true,
+        })
+    })
+}
+
+fn parse_formula(formula: &str) -> anyhow::Result<MathNode> {
+    math::parse(formula).with_context(|| format!("Could not parse the formula ${}$", formula))
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with(CODE_FENCE)
+}
+
+/// Splits `text` into lines of text and fenced code blocks. Blank lines outside of code blocks
+/// are dropped.
+pub fn parse_blocks(text: &str) -> anyhow::Result<Vec<Block<'_>>> {
+    Ok(parse_block_ends(text)?
+        .into_iter()
+        .map(|(block, _)| block)
+        .collect())
+}
+
+/// Splits off the first block of `text` when it is a line of text, returning that line and
+/// everything after it. Questions print this line next to their number.
+pub fn split_first_line(text: &str) -> Option<(&str, &str)> {
+    match parse_block_ends(text).ok()?.into_iter().next()? {
+        (Block::Text(line), end) => Some((line, &text[end..])),
+        _ => None,
+    }
+}
+
+/// Like [`parse_blocks`], with the byte offset in `text` at which each block ends.
+fn parse_block_ends(text: &str) -> anyhow::Result<Vec<(Block<'_>, usize)>> {
+    let mut blocks = vec![];
+    let mut code: Option<(usize, Vec<&str>)> = None;
+    let mut end = 0;
+
+    for (n, raw_line) in text.split_inclusive('\n').enumerate() {
+        end += raw_line.len();
+        let line = raw_line.strip_suffix('\n').unwrap_or(raw_line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        code = match code {
+            Some((_, lines)) if is_fence(line) => {
+                if line.trim() != CODE_FENCE {
+                    anyhow::bail!("Unexpected text after the closing ``` on line {}", n + 1);
+                }
+                blocks.push((Block::Code(lines), end));
+                None
+            }
+            Some((start, mut lines)) => {
+                lines.push(line);
+                Some((start, lines))
+            }
+            None if is_fence(line) => Some((n, vec![])),
+            None => {
+                let line = line.trim();
+                let display = line
+                    .strip_prefix("$$")
+                    .and_then(|l| l.strip_suffix("$$"))
+                    .filter(|l| !l.contains('$'));
+                if let Some(formula) = display {
+                    blocks.push((Block::Math(parse_formula(formula)?), end));
+                } else if !line.is_empty() {
+                    blocks.push((Block::Text(line), end));
+                }
+                None
+            }
+        };
+    }
+
+    if let Some((start, _)) = code {
+        anyhow::bail!(
+            "The code block opened on line {} is never closed",
+            start + 1
+        );
+    }
+    Ok(blocks)
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Script {
+    #[default]
+    Normal,
+    Sub,
+    Super,
+}
+
+/// A piece of a line of text.
+#[derive(PartialEq, Debug)]
+pub enum Inline {
+    Text(Span),
+    Math(MathNode),
+}
+
+/// A run of text with the same formatting.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub script: Script,
+}
+impl Span {
+    fn same_format(&self, other: &Span) -> bool {
+        self.bold == other.bold
+            && self.italic == other.italic
+            && self.underline == other.underline
+            && self.script == other.script
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    Bold,
+    Italic,
+    Underline,
+    Sub,
+    Super,
+}
+impl Marker {
+    const COUNT: usize = 5;
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Marker::Bold => "**",
+            Marker::Italic => "*",
+            Marker::Underline => "__",
+            Marker::Sub => "~",
+            Marker::Super => "^",
+        }
+    }
+
+    /// Emphasis can't start or end inside a word, so `a*b` and `snake__case` stay as they are.
+    fn is_emphasis(&self) -> bool {
+        matches!(self, Marker::Bold | Marker::Italic | Marker::Underline)
+    }
+}
+
+enum Token {
+    Text(String),
+    Math(String),
+    Marker {
+        marker: Marker,
+        can_open: bool,
+        can_close: bool,
+    },
+}
+
+/// Finds the `$` closing a formula opened at `start`. Like in pandoc, the formula can't start or
+/// end with whitespace and the closing `$` can't be followed by a digit, so prices like "$5 and
+/// $10" stay text. A `$` that doesn't open a formula is printed as it is.
+fn closing_dollar(chars: &[char], start: usize) -> Option<usize> {
+    if chars.get(start + 1).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+
+    let mut i = start + 2;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '$' => {
+                let closes = !chars[i - 1].is_whitespace()
+                    && !chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+                return closes.then_some(i);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn tokenize(line: &str) -> Vec<Token> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = vec![];
+    let mut text = String::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && matches!(chars.get(i + 1), Some('*' | '_' | '~' | '^' | '$' | '\\')) {
+            text.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+
+        if c == '$' {
+            if let Some(end) = closing_dollar(&chars, i) {
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(Token::Math(chars[i + 1..end].iter().collect()));
+                i = end + 1;
+                continue;
+            }
+        }
+
+        let marker = match c {
+            '*' if chars.get(i + 1) == Some(&'*') => Some(Marker::Bold),
+            '*' => Some(Marker::Italic),
+            '_' => {
+                // Longer runs of underscores are blanks to fill in, not underlines
+                let run = chars[i..].iter().take_while(|c| **c == '_').count();
+                if run != 2 {
+                    text.extend(&chars[i..i + run]);
+                    i += run;
+                    continue;
+                }
+                Some(Marker::Underline)
+            }
+            '~' => Some(Marker::Sub),
+            '^' => Some(Marker::Super),
+            _ => None,
+        };
+
+        match marker {
+            Some(marker) => {
+                let len = marker.as_str().len();
+                let before = i.checked_sub(1).map(|j| chars[j]);
+                let after = chars.get(i + len);
+                let in_word = |c: Option<&char>| {
+                    marker.is_emphasis() && c.is_some_and(|c| c.is_alphanumeric())
+                };
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(Token::Marker {
+                    marker,
+                    can_open: after.is_some_and(|c| !c.is_whitespace())
+                        && !in_word(before.as_ref()),
+                    can_close: before.is_some_and(|c| !c.is_whitespace()) && !in_word(after),
+                });
+                i += len;
+            }
+            None => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    tokens
+}
+
+/// Parses the inline markup of a line of text: `**bold**`, `*italic*`, `__underline__`,
+/// `~subscript~`, `^superscript^` and `$formulas$`. Markers without a partner are printed as
+/// they are and a backslash prints the next marker character literally. Subscripts and
+/// superscripts can't hold spaces, so `x^2 + y^2` stays as it is.
+pub fn parse_inline(line: &str) -> anyhow::Result<Vec<Inline>> {
+    let tokens = tokenize(line);
+
+    // Pair every opening marker with the next closing marker of the same kind
+    let mut paired = vec![false; tokens.len()];
+    let mut open: [Option<usize>; Marker::COUNT] = [None; Marker::COUNT];
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Marker {
+                marker,
+                can_open,
+                can_close,
+            } => {
+                let slot = &mut open[*marker as usize];
+                match slot {
+                    Some(start) if *can_close => {
+                        paired[*start] = true;
+                        paired[i] = true;
+                        *slot = None;
+                    }
+                    None if *can_open => *slot = Some(i),
+                    _ => {}
+                }
+            }
+            Token::Text(text) if text.contains(char::is_whitespace) => {
+                open[Marker::Sub as usize] = None;
+                open[Marker::Super as usize] = None;
+            }
+            _ => {}
+        }
+    }
+
+    let mut inlines: Vec<Inline> = vec![];
+    let mut active = [false; Marker::COUNT];
+    for (token, paired) in tokens.iter().zip(paired) {
+        let text = match token {
+            Token::Marker { marker, .. } if paired => {
+                active[*marker as usize] ^= true;
+                continue;
+            }
+            Token::Marker { marker, .. } => marker.as_str(),
+            Token::Text(text) => text.as_str(),
+            Token::Math(formula) => {
+                inlines.push(Inline::Math(parse_formula(formula)?));
+                continue;
+            }
+        };
+
+        let span = Span {
+            text: text.to_string(),
+            bold: active[Marker::Bold as usize],
+            italic: active[Marker::Italic as usize],
+            underline: active[Marker::Underline as usize],
+            script: if active[Marker::Super as usize] {
+                Script::Super
+            } else if active[Marker::Sub as usize] {
+                Script::Sub
+            } else {
+                Script::Normal
+            },
+        };
+        match inlines.last_mut() {
+            Some(Inline::Text(last)) if last.same_format(&span) => last.text.push_str(&span.text),
+            _ => inlines.push(Inline::Text(span)),
+        }
+    }
+    Ok(inlines)
+}
+
+/// Checks that every line of `text` parses, to report mistakes before generating a paper.
+pub fn validate(text: &str) -> anyhow::Result<()> {
+    for block in parse_blocks(text)? {
+        if let Block::Text(line) = block {
+            parse_inline(line)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(Span {
+            text: text.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn styled(text: &str, change: impl FnOnce(&mut Span)) -> Inline {
+        let mut span = Span {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        change(&mut span);
+        Inline::Text(span)
+    }
+
+    fn math(formula: &str) -> Inline {
+        Inline::Math(math::parse(formula).unwrap())
+    }
+
+    #[test]
+    fn markers() {
+        assert_eq!(
+            parse_inline("**bold**, *italic* and __underline__").unwrap(),
+            vec![
+                styled("bold", |s| s.bold = true),
+                text(", "),
+                styled("italic", |s| s.italic = true),
+                text(" and "),
+                styled("underline", |s| s.underline = true),
+            ]
+        );
+        assert_eq!(
+            parse_inline("H~2~O and x^2^").unwrap(),
+            vec![
+                text("H"),
+                styled("2", |s| s.script = Script::Sub),
+                text("O and x"),
+                styled("2", |s| s.script = Script::Super),
+            ]
+        );
+        assert_eq!(
+            parse_inline(r"\*not italic\* fill in ____").unwrap(),
+            vec![text("*not italic* fill in ____")]
+        );
+    }
+
+    #[test]
+    fn scripts_without_spaces() {
+        assert_eq!(
+            parse_inline("x^2 + y^2 = z^2").unwrap(),
+            vec![text("x^2 + y^2 = z^2")]
+        );
+        assert_eq!(
+            parse_inline("2^10 and 2^20 bytes").unwrap(),
+            vec![text("2^10 and 2^20 bytes")]
+        );
+        assert_eq!(
+            parse_inline("about ~5 or ~6 tries").unwrap(),
+            vec![text("about ~5 or ~6 tries")]
+        );
+        assert_eq!(
+            parse_inline("2^10^ bytes").unwrap(),
+            vec![
+                text("2"),
+                styled("10", |s| s.script = Script::Super),
+                text(" bytes"),
+            ]
+        );
+    }
+
+    #[test]
+    fn emphasis_inside_words() {
+        assert_eq!(
+            parse_inline("int *p = a*b;").unwrap(),
+            vec![text("int *p = a*b;")]
+        );
+        assert_eq!(parse_inline("a*b*c").unwrap(), vec![text("a*b*c")]);
+        assert_eq!(
+            parse_inline("snake__case__name").unwrap(),
+            vec![text("snake__case__name")]
+        );
+        assert_eq!(
+            parse_inline("(*italic*)").unwrap(),
+            vec![text("("), styled("italic", |s| s.italic = true), text(")"),]
+        );
+    }
+
+    #[test]
+    fn formulas() {
+        assert_eq!(
+            parse_inline("Solve $x^2 = 4$ for $x$").unwrap(),
+            vec![text("Solve "), math("x^2 = 4"), text(" for "), math("x"),]
+        );
+        assert_eq!(parse_inline(r"Costs \$5").unwrap(), vec![text("Costs $5")]);
+        assert!(parse_inline(r"$\frac{a}$").is_err());
+        assert!(parse_inline("$x^$").is_err());
+    }
+
+    #[test]
+    fn currency() {
+        for line in [
+            "Costs $5 each",
+            "$5 and $10",
+            "Between $5 and $ 10",
+            "A lone $",
+            "$",
+        ] {
+            assert_eq!(parse_inline(line).unwrap(), vec![text(line)], "{}", line);
+            assert!(!has_math(line), "{}", line);
+        }
+        assert_eq!(
+            parse_inline("$5, or $x$").unwrap(),
+            vec![text("$5, or "), math("x")]
+        );
+        assert!(has_math("Find $x$"));
+        assert!(has_math("$$\\sqrt{2}$$"));
+        assert!(!has_math("```\necho $HOME $PATH\n```"));
+    }
+
+    #[test]
+    fn first_line() {
+        assert_eq!(
+            split_first_line("\n  Is *x* > 1?  \r\nWhen x = 1?\n"),
+            Some(("Is *x* > 1?", "When x = 1?\n"))
+        );
+        assert_eq!(
+            split_first_line("x\n\n```\nx\n```"),
+            Some(("x", "\n```\nx\n```"))
+        );
+        assert_eq!(split_first_line("Only line"), Some(("Only line", "")));
+        assert_eq!(split_first_line("$$x$$\nText"), None);
+        assert_eq!(split_first_line("```\ncode\n```\nText"), None);
+        assert_eq!(split_first_line(""), None);
+    }
+}
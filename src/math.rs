@@ -0,0 +1,276 @@
+use anyhow::bail;
+
+/// A parsed formula, written in a small subset of LaTeX.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MathNode {
+    Symbol {
+        text: String,
+        /// Variables and lowercase Greek letters are printed in italics.
+        italic: bool,
+        /// Relations and binary operators get some space on both sides.
+        spaced: bool,
+    },
+    Row(Vec<MathNode>),
+    Frac(Box<MathNode>, Box<MathNode>),
+    Sqrt(Box<MathNode>),
+    Scripts {
+        base: Box<MathNode>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+}
+impl MathNode {
+    fn symbol(text: impl Into<String>, italic: bool, spaced: bool) -> Self {
+        Self::Symbol {
+            text: text.into(),
+            italic,
+            spaced,
+        }
+    }
+}
+
+#[rustfmt::skip]
+const GREEK: [(&str, char); 40] = [
+    ("alpha", 'α'), ("beta", 'β'), ("gamma", 'γ'), ("delta", 'δ'), ("epsilon", 'ε'),
+    ("varepsilon", 'ε'), ("zeta", 'ζ'), ("eta", 'η'), ("theta", 'θ'), ("iota", 'ι'),
+    ("kappa", 'κ'), ("lambda", 'λ'), ("mu", 'μ'), ("nu", 'ν'), ("xi", 'ξ'),
+    ("pi", 'π'), ("rho", 'ρ'), ("sigma", 'σ'), ("tau", 'τ'), ("upsilon", 'υ'),
+    ("phi", 'φ'), ("varphi", 'φ'), ("chi", 'χ'), ("psi", 'ψ'), ("omega", 'ω'),
+    ("Gamma", 'Γ'), ("Delta", 'Δ'), ("Theta", 'Θ'), ("Lambda", 'Λ'), ("Xi", 'Ξ'),
+    ("Pi", 'Π'), ("Sigma", 'Σ'), ("Upsilon", 'Υ'), ("Phi", 'Φ'), ("Psi", 'Ψ'),
+    ("Omega", 'Ω'), ("infty", '∞'), ("partial", '∂'), ("nabla", '∇'), ("degree", '°'),
+];
+
+#[rustfmt::skip]
+const OPERATORS: [(&str, char); 18] = [
+    ("cdot", '·'), ("times", '×'), ("div", '÷'), ("pm", '±'), ("mp", '∓'),
+    ("leq", '≤'), ("le", '≤'), ("geq", '≥'), ("ge", '≥'), ("neq", '≠'),
+    ("ne", '≠'), ("approx", '≈'), ("equiv", '≡'), ("to", '→'), ("rightarrow", '→'),
+    ("Rightarrow", '⇒'), ("in", '∈'), ("propto", '∝'),
+];
+
+const BIG_OPERATORS: [(&str, char); 3] = [("sum", '∑'), ("prod", '∏'), ("int", '∫')];
+
+const FUNCTIONS: [&str; 12] = [
+    "sin", "cos", "tan", "cot", "arcsin", "arccos", "arctan", "log", "ln", "lg", "exp", "lim",
+];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+impl Parser {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Parses atoms with their scripts until the end of the formula, or until the `}` closing
+    /// the group when `in_group` is set.
+    fn row(&mut self, in_group: bool) -> anyhow::Result<MathNode> {
+        let mut nodes: Vec<MathNode> = vec![];
+        loop {
+            match self.peek() {
+                None if in_group => bail!("Missing '}}' at the end of the formula"),
+                None => break,
+                Some('}') if in_group => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('}') => bail!("Unexpected '}}' at character {}", self.pos + 1),
+                Some(c @ ('^' | '_')) => {
+                    let Some(base) = nodes.pop() else {
+                        bail!(
+                            "'{}' at character {} has nothing before it",
+                            c,
+                            self.pos + 1
+                        );
+                    };
+                    self.pos += 1;
+                    let script = Box::new(self.atom()?);
+
+                    let (base, mut sub, mut sup) = match base {
+                        MathNode::Scripts { base, sub, sup } => (base, sub, sup),
+                        base => (Box::new(base), None, None),
+                    };
+                    let slot = if c == '^' { &mut sup } else { &mut sub };
+                    if slot.is_some() {
+                        bail!("Double '{}' at character {}, use braces", c, self.pos);
+                    }
+                    *slot = Some(script);
+                    nodes.push(MathNode::Scripts { base, sub, sup });
+                }
+                Some(_) => {
+                    let mut node = self.atom()?;
+                    // A sign at the start of the formula or after an operator is unary
+                    if let MathNode::Symbol { spaced, .. } = &mut node {
+                        let after_operator = match nodes.last() {
+                            None => true,
+                            Some(MathNode::Symbol { text, spaced, .. }) => *spaced || text == "(",
+                            Some(_) => false,
+                        };
+                        *spaced &= !after_operator;
+                    }
+                    nodes.push(node);
+                }
+            }
+        }
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => MathNode::Row(nodes),
+        })
+    }
+
+    /// Parses a single symbol, command or `{group}`.
+    fn atom(&mut self) -> anyhow::Result<MathNode> {
+        let Some(c) = self.peek() else {
+            bail!("The formula ends where something more was expected");
+        };
+        let start = self.pos;
+        self.pos += 1;
+
+        Ok(match c {
+            '{' => self.row(true)?,
+            '\\' => self.command(start)?,
+            '0'..='9' | '.' => {
+                let mut number = c.to_string();
+                while let Some(&c) = self.chars.get(self.pos) {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    number.push(c);
+                    self.pos += 1;
+                }
+                MathNode::symbol(number, false, false)
+            }
+            c if c.is_alphabetic() => MathNode::symbol(c, true, false),
+            '+' | '=' | '<' | '>' => MathNode::symbol(c, false, true),
+            '-' => MathNode::symbol('−', false, true),
+            '*' => MathNode::symbol('·', false, true),
+            '(' | ')' | '[' | ']' | '|' | ',' | ';' | ':' | '!' | '/' | '\'' => {
+                MathNode::symbol(c, false, false)
+            }
+            '}' => bail!("Missing argument before '}}' at character {}", start + 1),
+            c => bail!("Unexpected '{}' at character {}", c, start + 1),
+        })
+    }
+
+    fn command(&mut self, start: usize) -> anyhow::Result<MathNode> {
+        let mut name = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if !c.is_ascii_alphabetic() {
+                break;
+            }
+            name.push(c);
+            self.pos += 1;
+        }
+
+        if name.is_empty() {
+            // Escaped characters and spacing commands
+            let Some(&c) = self.chars.get(self.pos) else {
+                bail!("The formula ends with a lone '\\'");
+            };
+            self.pos += 1;
+            return Ok(match c {
+                ',' | ' ' | ';' => MathNode::symbol(' ', false, false),
+                '{' | '}' | '%' | '$' | '#' | '&' => MathNode::symbol(c, false, false),
+                c => bail!("Unknown command \\{} at character {}", c, start + 1),
+            });
+        }
+
+        let find = |table: &[(&str, char)]| table.iter().find(|(n, _)| *n == name).map(|e| e.1);
+        Ok(match name.as_str() {
+            "frac" => MathNode::Frac(Box::new(self.atom()?), Box::new(self.atom()?)),
+            "sqrt" => MathNode::Sqrt(Box::new(self.atom()?)),
+            _ => {
+                if let Some(c) = find(&GREEK) {
+                    MathNode::symbol(c, c.is_lowercase(), false)
+                } else if let Some(c) = find(&OPERATORS) {
+                    MathNode::symbol(c, false, true)
+                } else if let Some(c) = find(&BIG_OPERATORS) {
+                    MathNode::symbol(c, false, false)
+                } else if FUNCTIONS.contains(&name.as_str()) {
+                    MathNode::symbol(name, false, false)
+                } else {
+                    bail!("Unknown command \\{} at character {}", name, start + 1);
+                }
+            }
+        })
+    }
+}
+
+/// Parses a formula like `\frac{a}{b}`, `x^2` or `\sqrt{b^2 - 4ac}`.
+pub fn parse(formula: &str) -> anyhow::Result<MathNode> {
+    let mut parser = Parser {
+        chars: formula.chars().collect(),
+        pos: 0,
+    };
+    if parser.peek().is_none() {
+        bail!("The formula is empty");
+    }
+    parser.row(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(text: &str, italic: bool, spaced: bool) -> MathNode {
+        MathNode::symbol(text, italic, spaced)
+    }
+
+    #[test]
+    fn parses_formulas() {
+        assert_eq!(
+            parse("x^2 - 1").unwrap(),
+            MathNode::Row(vec![
+                MathNode::Scripts {
+                    base: Box::new(symbol("x", true, false)),
+                    sub: None,
+                    sup: Some(Box::new(symbol("2", false, false))),
+                },
+                symbol("−", false, true),
+                symbol("1", false, false),
+            ])
+        );
+        assert_eq!(
+            parse(r"\frac{\alpha}{2}").unwrap(),
+            MathNode::Frac(
+                Box::new(symbol("α", true, false)),
+                Box::new(symbol("2", false, false)),
+            )
+        );
+        assert_eq!(
+            parse("-3.5").unwrap(),
+            MathNode::Row(vec![symbol("−", false, false), symbol("3.5", false, false)])
+        );
+        assert_eq!(
+            parse("a_1^2").unwrap(),
+            parse("a^2_1").unwrap(),
+            "the order of scripts doesn't matter"
+        );
+        assert!(parse(r"\sqrt{b^2 - 4ac} \leq \infty").is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_formulas() {
+        for formula in [
+            "",
+            "   ",
+            "{x",
+            "x}",
+            "^2",
+            "x^",
+            "x^2^3",
+            r"\frac{a}",
+            r"\unknown",
+            r"x \",
+            "x # y",
+        ] {
+            assert!(parse(formula).is_err(), "{}", formula);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::ops::Range;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::data::{Project, Question, QuestionImage, Section};
+
+/// Picks the questions of a paper from the bank, returns their indices in bank order.
+///
+/// With an empty blueprint this is `max_questions` questions drawn from the whole bank, or the
+/// first ones when questions aren't randomized. Otherwise every rule draws its questions at
+/// random from the ones not taken by an earlier rule.
+fn select_questions(project: &Project, rng: &mut StdRng) -> anyhow::Result<Vec<usize>> {
+    if project.blueprint.is_empty() {
+        let count = project.questions.len();
+        let max = project.settings.max_questions as usize;
+        if !project.settings.randomize_questions {
+            return Ok((0..count.min(max)).collect());
+        }
+        let all: Vec<usize> = (0..count).collect();
+        let mut selected: Vec<usize> = all.choose_multiple(rng, max).copied().collect();
+        selected.sort_unstable();
+        return Ok(selected);
+    }
+
+    let mut picked = vec![false; project.questions.len()];
+    for (i, rule) in project.blueprint.iter().enumerate() {
+        let candidates: Vec<usize> = project
+            .questions
+            .iter()
+            .enumerate()
+            .filter(|(idx, q)| !picked[*idx] && rule.matches(q))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        if candidates.len() < rule.count as usize {
+            anyhow::bail!(
+                "Blueprint rule {} ({}) needs {} questions, but only {} are left in the bank",
+                i + 1,
+                rule.describe(),
+                rule.count,
+                candidates.len()
+            );
+        }
+
+        for idx in candidates.choose_multiple(rng, rule.count as usize) {
+            picked[*idx] = true;
+        }
+    }
+
+    let questions = picked.iter().enumerate().filter(|(_, picked)| **picked);
+    Ok(questions.map(|(idx, _)| idx).collect())
+}
+
+pub struct PaperAnswer {
+    pub text: String,
+    pub image: Option<QuestionImage>,
+    pub correct: bool,
+    /// Index of the answer in the question it came from. For selection questions the correct
+    /// answers are counted first, then the incorrect ones. For matching questions this is the
+    /// index of the pair the definition belongs to, and for ordering questions the correct
+    /// position of the item.
+    pub source: usize,
+}
+
+pub struct PaperQuestion {
+    pub question: Question,
+    /// Index of the question in the project's question bank.
+    pub index: usize,
+    /// The shuffled answers of the question, in the order they are printed.
+    pub answers: Vec<PaperAnswer>,
+}
+impl PaperQuestion {
+    fn new(question: Question, index: usize, rng: &mut StdRng) -> Self {
+        let mut answers = vec![];
+        match &question {
+            Question::Selection(q) => {
+                let correct = q.correct.iter().map(|answer| (answer, true));
+                let incorrect = q.incorrect.iter().map(|answer| (answer, false));
+                for (source, (answer, correct)) in correct.chain(incorrect).enumerate() {
+                    answers.push(PaperAnswer {
+                        text: answer.text.clone(),
+                        image: answer.image.clone(),
+                        correct,
+                        source,
+                    });
+                }
+                answers.shuffle(rng);
+            }
+            Question::Matching(q) => {
+                for (source, pair) in q.pairs.iter().enumerate() {
+                    answers.push(PaperAnswer {
+                        text: pair.definition.clone(),
+                        image: None,
+                        correct: true,
+                        source,
+                    });
+                }
+                answers.shuffle(rng);
+            }
+            Question::Ordering(q) => {
+                for (source, item) in q.items.iter().enumerate() {
+                    answers.push(PaperAnswer {
+                        text: item.clone(),
+                        image: None,
+                        correct: true,
+                        source,
+                    });
+                }
+                answers.shuffle(rng);
+            }
+            Question::Input(_)
+            | Question::TrueFalse(_)
+            | Question::Cloze(_)
+            | Question::Numeric(_) => {}
+        }
+
+        Self {
+            question,
+            index,
+            answers,
+        }
+    }
+
+    /// Returns the position of the answer that came from `source` in the question.
+    pub fn position_of(&self, source: usize) -> Option<usize> {
+        self.answers.iter().position(|a| a.source == source)
+    }
+}
+
+/// A run of consecutive questions printed under the same section heading.
+pub struct PaperPart {
+    /// `None` for the questions outside of sections, which come first.
+    pub section: Option<Section>,
+    /// Indices into `Paper::questions`.
+    pub questions: Range<usize>,
+}
+
+/// The questions and answers of a single paper, in the order they are printed.
+///
+/// Both the student paper and the answer key are rendered from the same `Paper`, so the letters
+/// in the key always match the shuffled answers.
+pub struct Paper {
+    /// The group letter printed in the header when generating multiple variants.
+    pub variant: Option<char>,
+    /// The seed all shuffles of this paper were made with. Generating a paper from the same
+    /// project with the same seed gives the exact same order.
+    pub seed: u64,
+    /// Questions are numbered continuously across parts.
+    pub questions: Vec<PaperQuestion>,
+    pub parts: Vec<PaperPart>,
+}
+impl Paper {
+    pub fn new(project: &Project, variant: Option<char>, seed: u64) -> anyhow::Result<Self> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let rng = &mut rng;
+
+        let selected = select_questions(project, rng)?;
+        if let Some(q) = selected
+            .iter()
+            .map(|idx| &project.questions[*idx])
+            .find(|q| {
+                let section = &q.meta().section;
+                !section.is_empty() && !project.sections.iter().any(|s| &s.title == section)
+            })
+        {
+            anyhow::bail!(
+                "Question '{}' is in section '{}', which does not exist",
+                q.get_short_title(),
+                q.meta().section
+            );
+        }
+
+        let mut questions = vec![];
+        let mut parts = vec![];
+
+        let unsectioned = std::iter::once((None, project.settings.randomize_questions));
+        let sections = project.sections.iter().map(|s| (Some(s), s.shuffle));
+        for (section, shuffle) in unsectioned.chain(sections) {
+            let title = section.map_or("", |s| s.title.as_str());
+            let mut part: Vec<usize> = selected
+                .iter()
+                .copied()
+                .filter(|idx| project.questions[*idx].meta().section == title)
+                .collect();
+            if part.is_empty() {
+                continue;
+            }
+            if shuffle {
+                part.shuffle(rng);
+            }
+
+            let start = questions.len();
+            for idx in part {
+                let question = project.questions[idx].clone();
+                questions.push(PaperQuestion::new(question, idx, rng));
+            }
+            parts.push(PaperPart {
+                section: section.cloned(),
+                questions: start..questions.len(),
+            });
+        }
+
+        Ok(Self {
+            variant,
+            seed,
+            questions,
+            parts,
+        })
+    }
+
+    pub fn max_points(&self) -> usize {
+        self.points(0..self.questions.len())
+    }
+
+    pub fn points(&self, range: Range<usize>) -> usize {
+        self.questions[range]
+            .iter()
+            .map(|q| q.question.get_points() as usize)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InputQuestion;
+    use crate::settings::Settings;
+
+    fn project(randomize_questions: bool) -> Project {
+        let question = |n: usize| {
+            Question::Input(InputQuestion {
+                question: format!("Question {}", n),
+                ..Default::default()
+            })
+        };
+        Project {
+            settings: Settings {
+                max_questions: 3,
+                randomize_questions,
+                ..Default::default()
+            },
+            questions: (0..10).map(question).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The bank indices of the questions on the paper made with `seed`.
+    fn subset(project: &Project, seed: u64) -> Vec<usize> {
+        let paper = Paper::new(project, None, seed).unwrap();
+        let mut indices: Vec<usize> = paper.questions.iter().map(|q| q.index).collect();
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn draws_from_the_whole_bank() {
+        let project = project(true);
+        let subsets: Vec<Vec<usize>> = (0..20).map(|seed| subset(&project, seed)).collect();
+        assert!(subsets.iter().all(|s| s.len() == 3));
+        assert!(subsets.iter().any(|s| s != &subsets[0]));
+        assert!(subsets.iter().flatten().any(|idx| *idx >= 3));
+        // The same seed gives the same paper
+        assert_eq!(subset(&project, 7), subset(&project, 7));
+    }
+
+    #[test]
+    fn takes_the_first_questions_in_order() {
+        let project = project(false);
+        let paper = Paper::new(&project, None, 7).unwrap();
+        let indices: Vec<usize> = paper.questions.iter().map(|q| q.index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+    }
+}
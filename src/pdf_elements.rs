@@ -2,10 +2,12 @@ use rckive_genpdf::{
     elements::{BulletPoint, LinearLayout},
     error::Error,
     render,
-    style::Style,
-    Context, Element, Position, RenderResult,
+    style::{Color, LineStyle, Style, StyledString},
+    Context, Element, Mm, Position, RenderResult, Size,
 };
 
+use crate::{markup::Script, math::MathNode};
+
 pub struct AlphabeticOrderedList {
     layout: LinearLayout,
     number: u32,
@@ -23,11 +25,15 @@ impl AlphabeticOrderedList {
         }
     }
 
-    pub fn push<E: Element + 'static>(&mut self, element: E) {
+    /// Returns the letter used for the bullet at `index` of a list starting at `start_char`.
+    pub fn letter(start_char: char, index: u32) -> char {
         // TODO: make this safer
+        char::from_u32(start_char as u32 + index).unwrap()
+    }
+
+    pub fn push<E: Element + 'static>(&mut self, element: E) {
         let mut point = BulletPoint::new(element);
-        let ch = self.start_char as u32 + self.number;
-        let ch = char::from_u32(ch).unwrap();
+        let ch = Self::letter(self.start_char, self.number);
         point.set_bullet(format!("{})", ch));
         self.layout.push(point);
         self.number += 1;
@@ -45,6 +51,20 @@ impl Element for AlphabeticOrderedList {
     }
 }
 
+/// An element whose type is only known at runtime, so it can go where a concrete element is
+/// expected.
+pub struct Boxed(pub Box<dyn Element>);
+impl Element for Boxed {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        self.0.render(context, area, style)
+    }
+}
+
 pub struct CharRepeat(char);
 impl CharRepeat {
     pub fn new(ch: char) -> Self {
@@ -106,12 +126,14 @@ impl Element for SplitElement {
         area: render::Area<'_>,
         style: rckive_genpdf::style::Style,
     ) -> Result<RenderResult, rckive_genpdf::error::Error> {
-        if self.split_size == 0.0 {
+        let (left, mut right) = if self.split_size == 0.0 {
             let left = self.left.render(context, area.clone(), style)?;
 
             let mut right_area = area.clone();
             right_area.add_offset(Position::new(left.size.width, 0.0));
-            self.right.render(context, right_area, style)
+            let mut right = self.right.render(context, right_area, style)?;
+            right.size.width += left.size.width;
+            (left, right)
         } else {
             let left_width = area.size().width * self.split_size;
 
@@ -120,8 +142,940 @@ impl Element for SplitElement {
             let mut right = area.clone();
             right.add_offset(Position::new(left_width, 0.0));
 
-            self.left.render(context, left, style)?;
-            self.right.render(context, right, style)
+            let left = self.left.render(context, left, style)?;
+            let mut right = self.right.render(context, right, style)?;
+            right.size.width += left_width;
+            (left, right)
+        };
+
+        // The taller side decides how much space the split takes up
+        right.size.height = right.size.height.max(left.size.height);
+        right.has_more |= left.has_more;
+        Ok(right)
+    }
+}
+
+fn draw_rect(area: &render::Area<'_>, position: Position, size: Size, line_style: LineStyle) {
+    let (x, y) = (position.x, position.y);
+    area.draw_line(
+        [
+            Position::new(x, y),
+            Position::new(x + size.width, y),
+            Position::new(x + size.width, y + size.height),
+            Position::new(x, y + size.height),
+            Position::new(x, y),
+        ],
+        line_style,
+    );
+}
+
+/// A row of empty boxes for the student to tick, each followed by a label.
+pub struct TickBoxes {
+    labels: Vec<String>,
+}
+impl TickBoxes {
+    pub fn new(labels: Vec<String>) -> Self {
+        Self { labels }
+    }
+}
+impl Element for TickBoxes {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let metrics = style.metrics(&context.font_cache);
+        if metrics.line_height > area.size().height {
+            result.has_more = true;
+            return Ok(result);
+        }
+
+        let side = metrics.ascent * 0.8;
+        let gap = style.char_width(&context.font_cache, ' ');
+
+        let mut x = Mm::from(0.0);
+        for label in self.labels.iter() {
+            draw_rect(
+                &area,
+                Position::new(x, metrics.ascent - side),
+                Size::new(side, side),
+                LineStyle::new(),
+            );
+            x += side + gap;
+
+            area.print_str(&context.font_cache, Position::new(x, 0), style, label)?;
+            x += style.str_width(&context.font_cache, label) + gap * 4.0;
+        }
+
+        result.size = Size::new(x, metrics.line_height);
+        Ok(result)
+    }
+}
+
+/// An empty box, one line high, for the student to write a short answer in.
+pub struct AnswerBox {
+    width: Mm,
+}
+impl AnswerBox {
+    pub fn new(width: impl Into<Mm>) -> Self {
+        Self {
+            width: width.into(),
+        }
+    }
+}
+impl Element for AnswerBox {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let line_height = style.line_height(&context.font_cache);
+        if line_height > area.size().height {
+            result.has_more = true;
+            return Ok(result);
+        }
+
+        draw_rect(
+            &area,
+            Position::default(),
+            Size::new(self.width, line_height),
+            LineStyle::new(),
+        );
+
+        let gap = style.char_width(&context.font_cache, ' ');
+        result.size = Size::new(self.width + gap, line_height);
+        Ok(result)
+    }
+}
+
+/// Lines of code printed as they are, keeping indentation and line breaks. Lines too long for the
+/// page are wrapped, and the block continues on the next page if it doesn't fit.
+pub struct CodeBlock {
+    lines: Vec<String>,
+    line_numbers: bool,
+    frame: bool,
+    /// Rows already rendered on previous pages.
+    rendered: usize,
+}
+impl CodeBlock {
+    const TAB: &'static str = "    ";
+    const PADDING: f64 = 1.5;
+
+    pub fn new(lines: &[&str], line_numbers: bool, frame: bool) -> Self {
+        Self {
+            lines: lines.iter().map(|l| l.replace('\t', Self::TAB)).collect(),
+            line_numbers,
+            frame,
+            rendered: 0,
+        }
+    }
+
+    /// Splits the lines into rows of at most `width` characters. Only the first row of a line
+    /// gets its number.
+    fn rows(&self, width: usize) -> Vec<(Option<usize>, String)> {
+        let mut rows = vec![];
+        for (n, line) in self.lines.iter().enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                rows.push((Some(n + 1), String::new()));
+            }
+            for (i, chunk) in chars.chunks(width).enumerate() {
+                rows.push(((i == 0).then_some(n + 1), chunk.iter().collect()));
+            }
+        }
+        rows
+    }
+}
+impl Element for CodeBlock {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let line_height = style.line_height(&context.font_cache);
+        let char_width = style.char_width(&context.font_cache, ' ');
+        let padding = Mm::from(if self.frame { Self::PADDING } else { 0.0 });
+
+        let digits = self.lines.len().to_string().len();
+        let gutter = if self.line_numbers {
+            char_width * (digits + 1) as f64
+        } else {
+            Mm::from(0.0)
+        };
+        let width = area.size().width - padding * 2.0 - gutter;
+        let chars_per_row = (f64::from(width) / f64::from(char_width)).max(1.0) as usize;
+
+        let rows = self.rows(chars_per_row);
+        let number_style = style.with_color(Color::Greyscale(128));
+
+        let mut y = padding;
+        for (number, row) in rows.iter().skip(self.rendered) {
+            if y + line_height + padding > area.size().height {
+                result.has_more = true;
+                break;
+            }
+            if let Some(number) = number.filter(|_| self.line_numbers) {
+                area.print_str(
+                    &context.font_cache,
+                    Position::new(padding, y),
+                    number_style,
+                    format!("{:>1$}", number, digits),
+                )?;
+            }
+            area.print_str(
+                &context.font_cache,
+                Position::new(padding + gutter, y),
+                style,
+                row,
+            )?;
+            y += line_height;
+            self.rendered += 1;
+        }
+
+        if y == padding && result.has_more {
+            // Nothing fit, let the block start on the next page
+            return Ok(result);
+        }
+
+        let size = Size::new(area.size().width, y + padding);
+        if self.frame {
+            draw_rect(
+                &area,
+                Position::default(),
+                size,
+                LineStyle::new()
+                    .with_color(Color::Greyscale(180))
+                    .with_thickness(0.2),
+            );
+        }
+
+        result.size = size;
+        Ok(result)
+    }
+}
+
+/// A piece of `RichText` in a single style.
+pub struct RichSpan {
+    pub text: StyledString,
+    pub underline: bool,
+    pub script: Script,
+}
+
+enum RichItem {
+    Text(RichSpan),
+    /// An inline formula with the style it's printed in.
+    Formula(Formula, Style),
+}
+
+/// A word, or the part of one that falls in a single item, placed on a line.
+struct PlacedPiece {
+    item: usize,
+    /// Empty for formulas.
+    text: String,
+    x: Mm,
+    width: Mm,
+    /// Covers the space before the next word too when it continues the same underlined span.
+    underline_width: Mm,
+}
+
+/// Wrapped text that, unlike `Paragraph`, can underline and raise or lower parts of itself and
+/// hold formulas.
+#[derive(Default)]
+pub struct RichText {
+    items: Vec<RichItem>,
+    /// Lines already rendered on previous pages.
+    rendered: usize,
+}
+impl RichText {
+    const SCRIPT_SCALE: f64 = 0.7;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, span: RichSpan) {
+        self.items.push(RichItem::Text(span));
+    }
+
+    pub fn push_styled(&mut self, text: impl Into<String>, style: Style) {
+        self.push(RichSpan {
+            text: StyledString::new(text.into(), style),
+            underline: false,
+            script: Script::Normal,
+        });
+    }
+
+    pub fn push_formula(&mut self, formula: Formula, style: Style) {
+        self.items.push(RichItem::Formula(formula, style));
+    }
+
+    /// The style of an item without the smaller font size of sub and superscripts.
+    fn base_style(&self, item: usize, style: Style) -> Style {
+        match &self.items[item] {
+            RichItem::Text(span) => style.and(span.text.style),
+            RichItem::Formula(_, formula_style) => style.and(*formula_style),
+        }
+    }
+
+    fn item_style(&self, item: usize, style: Style) -> Style {
+        let base = self.base_style(item, style);
+        match &self.items[item] {
+            RichItem::Text(span) if span.script != Script::Normal => {
+                let size = f64::from(base.font_size()) * Self::SCRIPT_SCALE;
+                base.with_font_size(size.round() as u8)
+            }
+            _ => base,
         }
     }
+
+    /// Splits the items into words, each made of the pieces of the items it crosses. Formulas
+    /// are never split.
+    fn words(&self) -> Vec<Vec<(usize, String)>> {
+        let mut words = vec![];
+        let mut word = vec![];
+        for (idx, item) in self.items.iter().enumerate() {
+            let span = match item {
+                RichItem::Text(span) => span,
+                RichItem::Formula(..) => {
+                    word.push((idx, String::new()));
+                    continue;
+                }
+            };
+
+            let mut piece = String::new();
+            for c in span.text.s.chars() {
+                if !c.is_whitespace() {
+                    piece.push(c);
+                    continue;
+                }
+                if !piece.is_empty() {
+                    word.push((idx, std::mem::take(&mut piece)));
+                }
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            if !piece.is_empty() {
+                word.push((idx, piece));
+            }
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+        words
+    }
+
+    fn piece_width(&self, context: &Context, item: usize, text: &str, style: Style) -> Mm {
+        let style = self.item_style(item, style);
+        match &self.items[item] {
+            RichItem::Text(_) => style.str_width(&context.font_cache, text),
+            RichItem::Formula(formula, _) => formula.measure(context, style).width,
+        }
+    }
+
+    fn lines(&self, context: &Context, width: Mm, style: Style) -> Vec<Vec<PlacedPiece>> {
+        let space = style.char_width(&context.font_cache, ' ');
+
+        let mut lines = vec![];
+        let mut line: Vec<PlacedPiece> = vec![];
+        let mut x = Mm::from(0.0);
+        for word in self.words() {
+            let widths: Vec<Mm> = word
+                .iter()
+                .map(|(item, text)| self.piece_width(context, *item, text, style))
+                .collect();
+            let word_width = widths.iter().fold(Mm::from(0.0), |sum, w| sum + *w);
+
+            if !line.is_empty() && x + space + word_width > width {
+                lines.push(std::mem::take(&mut line));
+                x = Mm::from(0.0);
+            }
+            if let Some(last) = line.last_mut() {
+                if last.item == word[0].0 {
+                    last.underline_width += space;
+                }
+                x += space;
+            }
+
+            for ((item, text), width) in word.into_iter().zip(widths) {
+                line.push(PlacedPiece {
+                    item,
+                    text,
+                    x,
+                    width,
+                    underline_width: width,
+                });
+                x += width;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+}
+impl Element for RichText {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let mut y = Mm::from(0.0);
+        for line in self
+            .lines(context, area.size().width, style)
+            .iter()
+            .skip(self.rendered)
+        {
+            // Sub and superscripts are placed relative to the full size text of the line
+            let metrics = line
+                .iter()
+                .map(|p| self.base_style(p.item, style).metrics(&context.font_cache))
+                .fold(style.metrics(&context.font_cache), |a, b| a.max(&b));
+
+            // Formulas can reach above and below the text around them
+            let mut ascent = metrics.ascent;
+            let mut below = metrics.line_height - metrics.ascent;
+            for piece in line.iter() {
+                if let RichItem::Formula(formula, _) = &self.items[piece.item] {
+                    let item_style = self.item_style(piece.item, style);
+                    let size = formula.measure(context, item_style);
+                    ascent = ascent.max(size.ascent);
+                    below = below.max(size.descent + Formula::em(item_style) * 0.3);
+                }
+            }
+            let line_height = ascent + below;
+
+            if y + line_height > area.size().height {
+                result.has_more = true;
+                break;
+            }
+
+            for piece in line.iter() {
+                let item_style = self.item_style(piece.item, style);
+                let span = match &self.items[piece.item] {
+                    RichItem::Text(span) => span,
+                    RichItem::Formula(formula, _) => {
+                        formula.draw(context, &area, item_style, piece.x, y + ascent)?;
+                        continue;
+                    }
+                };
+
+                let piece_ascent = item_style.metrics(&context.font_cache).ascent;
+                let shift = match span.script {
+                    Script::Normal => Mm::from(0.0),
+                    Script::Sub => metrics.ascent * 0.2,
+                    Script::Super => metrics.ascent * -0.4,
+                };
+
+                area.print_str(
+                    &context.font_cache,
+                    Position::new(piece.x, y + ascent - piece_ascent + shift),
+                    item_style,
+                    &piece.text,
+                )?;
+
+                if span.underline {
+                    let underline_y = y + ascent + metrics.ascent * 0.12;
+                    area.draw_line(
+                        [
+                            Position::new(piece.x, underline_y),
+                            Position::new(piece.x + piece.underline_width, underline_y),
+                        ],
+                        LineStyle::new()
+                            .with_color(item_style.color().unwrap_or(Color::Rgb(0, 0, 0)))
+                            .with_thickness(0.2),
+                    );
+                }
+            }
+
+            let width = line.last().map_or(Mm::from(0.0), |p| p.x + p.width);
+            result.size.width = result.size.width.max(width);
+            y += line_height;
+            self.rendered += 1;
+        }
+
+        result.size.height = y;
+        Ok(result)
+    }
+}
+
+/// The size of a typeset formula, measured from its baseline.
+#[derive(Clone, Copy)]
+pub struct MathBox {
+    pub width: Mm,
+    pub ascent: Mm,
+    pub descent: Mm,
+}
+
+/// A typeset formula. On its own it is printed centered on a line, inside `RichText` it flows
+/// with the text.
+pub struct Formula {
+    node: MathNode,
+}
+impl Formula {
+    const SCRIPT_SCALE: f64 = 0.7;
+    const FRAC_SCALE: f64 = 0.85;
+
+    pub fn new(node: MathNode) -> Self {
+        Self { node }
+    }
+
+    /// The font size of `style` in millimeters, which all spacing is relative to.
+    fn em(style: Style) -> Mm {
+        Mm::from(f64::from(style.font_size()) * 0.352_778)
+    }
+
+    fn scaled(style: Style, scale: f64) -> Style {
+        let size = (f64::from(style.font_size()) * scale).round() as u8;
+        style.with_font_size(size.max(5))
+    }
+
+    fn symbol_style(style: Style, italic: bool) -> Style {
+        if italic {
+            style.italic()
+        } else {
+            style
+        }
+    }
+
+    /// Places scripts relative to the baseline of `base`, returns how far the superscript is
+    /// raised and the subscript lowered.
+    fn script_shifts(base: MathBox, sup: Option<MathBox>, em: Mm) -> (Mm, Mm) {
+        let sup_ascent = sup.map_or(Mm::from(0.0), |sup| sup.ascent);
+        let sup_shift = (base.ascent - sup_ascent * 0.5).max(em * 0.4);
+        let sub_shift = (base.descent + em * 0.05).max(em * 0.2);
+        (sup_shift, sub_shift)
+    }
+
+    pub fn measure(&self, context: &Context, style: Style) -> MathBox {
+        Self::measure_node(&self.node, context, style)
+    }
+
+    fn measure_node(node: &MathNode, context: &Context, style: Style) -> MathBox {
+        let em = Self::em(style);
+        match node {
+            MathNode::Symbol {
+                text,
+                italic,
+                spaced,
+            } => {
+                let mut width =
+                    Self::symbol_style(style, *italic).str_width(&context.font_cache, text);
+                if *spaced {
+                    width += em * 0.5;
+                }
+                MathBox {
+                    width,
+                    ascent: em * 0.72,
+                    descent: em * 0.22,
+                }
+            }
+            MathNode::Row(nodes) => nodes.iter().fold(
+                MathBox {
+                    width: Mm::from(0.0),
+                    ascent: em * 0.72,
+                    descent: em * 0.22,
+                },
+                |row, node| {
+                    let node = Self::measure_node(node, context, style);
+                    MathBox {
+                        width: row.width + node.width,
+                        ascent: row.ascent.max(node.ascent),
+                        descent: row.descent.max(node.descent),
+                    }
+                },
+            ),
+            MathNode::Frac(num, den) => {
+                let small = Self::scaled(style, Self::FRAC_SCALE);
+                let num = Self::measure_node(num, context, small);
+                let den = Self::measure_node(den, context, small);
+                MathBox {
+                    width: num.width.max(den.width) + em * 0.3,
+                    ascent: em * 0.4 + num.ascent + num.descent,
+                    descent: den.ascent + den.descent - em * 0.1,
+                }
+            }
+            MathNode::Sqrt(inner) => {
+                let inner = Self::measure_node(inner, context, style);
+                MathBox {
+                    width: em * 0.6 + inner.width,
+                    ascent: inner.ascent + em * 0.2,
+                    descent: inner.descent,
+                }
+            }
+            MathNode::Scripts { base, sub, sup } => {
+                let small = Self::scaled(style, Self::SCRIPT_SCALE);
+                let base = Self::measure_node(base, context, style);
+                let sup = sup.as_ref().map(|n| Self::measure_node(n, context, small));
+                let sub = sub.as_ref().map(|n| Self::measure_node(n, context, small));
+                let (sup_shift, sub_shift) = Self::script_shifts(base, sup, em);
+
+                let mut size = base;
+                let mut scripts_width = Mm::from(0.0);
+                if let Some(sup) = sup {
+                    scripts_width = scripts_width.max(sup.width);
+                    size.ascent = size.ascent.max(sup_shift + sup.ascent);
+                }
+                if let Some(sub) = sub {
+                    scripts_width = scripts_width.max(sub.width);
+                    size.descent = size.descent.max(sub_shift + sub.descent);
+                }
+                size.width += scripts_width + em * 0.05;
+                size
+            }
+        }
+    }
+
+    pub fn draw(
+        &self,
+        context: &Context,
+        area: &render::Area<'_>,
+        style: Style,
+        x: Mm,
+        baseline: Mm,
+    ) -> Result<(), Error> {
+        Self::draw_node(&self.node, context, area, style, x, baseline)
+    }
+
+    fn draw_node(
+        node: &MathNode,
+        context: &Context,
+        area: &render::Area<'_>,
+        style: Style,
+        x: Mm,
+        baseline: Mm,
+    ) -> Result<(), Error> {
+        let em = Self::em(style);
+        let line_style = LineStyle::new()
+            .with_color(style.color().unwrap_or(Color::Rgb(0, 0, 0)))
+            .with_thickness(em * 0.05);
+
+        match node {
+            MathNode::Symbol {
+                text,
+                italic,
+                spaced,
+            } => {
+                let style = Self::symbol_style(style, *italic);
+                let x = if *spaced { x + em * 0.25 } else { x };
+                let ascent = style.metrics(&context.font_cache).ascent;
+                area.print_str(
+                    &context.font_cache,
+                    Position::new(x, baseline - ascent),
+                    style,
+                    text,
+                )?;
+            }
+            MathNode::Row(nodes) => {
+                let mut x = x;
+                for node in nodes {
+                    Self::draw_node(node, context, area, style, x, baseline)?;
+                    x += Self::measure_node(node, context, style).width;
+                }
+            }
+            MathNode::Frac(num, den) => {
+                let small = Self::scaled(style, Self::FRAC_SCALE);
+                let size = Self::measure_node(node, context, style);
+                let num_size = Self::measure_node(num, context, small);
+                let den_size = Self::measure_node(den, context, small);
+
+                // The bar sits at the height of a minus sign
+                let axis = baseline - em * 0.25;
+                area.draw_line(
+                    [
+                        Position::new(x + em * 0.05, axis),
+                        Position::new(x + size.width - em * 0.05, axis),
+                    ],
+                    line_style,
+                );
+
+                let num_x = x + (size.width - num_size.width) * 0.5;
+                let num_baseline = axis - em * 0.15 - num_size.descent;
+                Self::draw_node(num, context, area, small, num_x, num_baseline)?;
+
+                let den_x = x + (size.width - den_size.width) * 0.5;
+                let den_baseline = axis + em * 0.15 + den_size.ascent;
+                Self::draw_node(den, context, area, small, den_x, den_baseline)?;
+            }
+            MathNode::Sqrt(inner) => {
+                let size = Self::measure_node(node, context, style);
+                let top = baseline - size.ascent + em * 0.05;
+                let bottom = baseline + size.descent;
+                let height = bottom - top;
+                area.draw_line(
+                    [
+                        Position::new(x + em * 0.05, bottom - height * 0.4),
+                        Position::new(x + em * 0.15, bottom - height * 0.45),
+                        Position::new(x + em * 0.3, bottom),
+                        Position::new(x + em * 0.5, top),
+                        Position::new(x + size.width, top),
+                    ],
+                    line_style,
+                );
+                Self::draw_node(inner, context, area, style, x + em * 0.55, baseline)?;
+            }
+            MathNode::Scripts { base, sub, sup } => {
+                let small = Self::scaled(style, Self::SCRIPT_SCALE);
+                let base_size = Self::measure_node(base, context, style);
+                let sup_size = sup.as_ref().map(|n| Self::measure_node(n, context, small));
+                let (sup_shift, sub_shift) = Self::script_shifts(base_size, sup_size, em);
+
+                Self::draw_node(base, context, area, style, x, baseline)?;
+                let x = x + base_size.width + em * 0.05;
+                if let Some(sup) = sup {
+                    Self::draw_node(sup, context, area, small, x, baseline - sup_shift)?;
+                }
+                if let Some(sub) = sub {
+                    Self::draw_node(sub, context, area, small, x, baseline + sub_shift)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+impl Element for Formula {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let size = self.measure(context, style);
+        let padding = Self::em(style) * 0.3;
+        let height = size.ascent + size.descent + padding * 2.0;
+        if height > area.size().height {
+            result.has_more = true;
+            return Ok(result);
+        }
+
+        let x = ((area.size().width - size.width) * 0.5).max(Mm::from(0.0));
+        self.draw(context, &area, style, x, padding + size.ascent)?;
+
+        result.size = Size::new(area.size().width, height);
+        Ok(result)
+    }
+}
+
+pub const BUBBLE_RADIUS: f64 = 2.2;
+pub const BUBBLE_SPACING: f64 = 6.0;
+
+fn draw_circle(area: &render::Area<'_>, center: Position, radius: Mm, line_style: LineStyle) {
+    const SEGMENTS: usize = 32;
+    let points = (0..=SEGMENTS).map(|i| {
+        let angle = i as f64 / SEGMENTS as f64 * std::f64::consts::TAU;
+        Position::new(
+            center.x + radius * angle.cos(),
+            center.y + radius * angle.sin(),
+        )
+    });
+    area.draw_line(points, line_style);
+}
+
+/// Prints `text` centered on `center`.
+fn print_centered(
+    area: &render::Area<'_>,
+    context: &Context,
+    style: Style,
+    center: Position,
+    text: &str,
+) -> Result<(), Error> {
+    let width = style.str_width(&context.font_cache, text);
+    let metrics = style.metrics(&context.font_cache);
+    // Digits and lowercase letters sit a bit below the middle of the ascent
+    let top = center.y - metrics.ascent * 0.7;
+    area.print_str(
+        &context.font_cache,
+        Position::new(center.x - width * 0.5, top),
+        style,
+        text,
+    )?;
+    Ok(())
+}
+
+/// A circle with a small label inside it, for the student to fill in.
+fn draw_bubble(
+    area: &render::Area<'_>,
+    context: &Context,
+    style: Style,
+    center: Position,
+    label: &str,
+) -> Result<(), Error> {
+    draw_circle(area, center, Mm::from(BUBBLE_RADIUS), LineStyle::new());
+    let label_style = style.with_font_size(7).with_color(Color::Greyscale(100));
+    print_centered(area, context, label_style, center, label)
+}
+
+/// Solid squares in the corners of the page for scanners to align the answer sheet with.
+///
+/// They are drawn in the corners of the area the element gets, so it should come first on a new
+/// page. It takes up no space, pad the content after it to keep it clear of the marks.
+pub struct RegistrationMarks {
+    size: Mm,
+}
+impl RegistrationMarks {
+    pub fn new(size: impl Into<Mm>) -> Self {
+        Self { size: size.into() }
+    }
+}
+impl Element for RegistrationMarks {
+    fn render(
+        &mut self,
+        _context: &Context,
+        area: render::Area<'_>,
+        _style: Style,
+    ) -> Result<RenderResult, Error> {
+        // A line as thick as it is long makes a filled square
+        let line_style = LineStyle::new().with_thickness(self.size);
+        let half = self.size * 0.5;
+        let (width, height) = (area.size().width, area.size().height);
+        for (x, y) in [
+            (half, half),
+            (width - half, half),
+            (half, height - half),
+            (width - half, height - half),
+        ] {
+            area.draw_line(
+                [Position::new(x - half, y), Position::new(x + half, y)],
+                line_style,
+            );
+        }
+
+        Ok(RenderResult::default())
+    }
+}
+
+/// Columns of bubbles numbered 0-9, one column per digit of the student's ID, each under a box
+/// to write the digit in.
+pub struct IdBubbles {
+    digits: u8,
+}
+impl IdBubbles {
+    pub fn new(digits: u8) -> Self {
+        Self { digits }
+    }
+}
+impl Element for IdBubbles {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let spacing = Mm::from(BUBBLE_SPACING);
+        let height = spacing * 11.0;
+        if height > area.size().height {
+            result.has_more = true;
+            return Ok(result);
+        }
+
+        for column in 0..self.digits {
+            let x = spacing * (f64::from(column) + 0.5);
+            draw_rect(
+                &area,
+                Position::new(x - spacing * 0.4, Mm::from(0.0)),
+                Size::new(spacing * 0.8, spacing * 0.8),
+                LineStyle::new(),
+            );
+            for digit in 0..10 {
+                let y = spacing * (f64::from(digit) + 1.5);
+                draw_bubble(
+                    &area,
+                    context,
+                    style,
+                    Position::new(x, y),
+                    &digit.to_string(),
+                )?;
+            }
+        }
+
+        result.size = Size::new(spacing * f64::from(self.digits), height);
+        Ok(result)
+    }
+}
+
+/// A row of answer bubbles for each question, laid out in as many columns as fit on the page.
+pub struct BubbleGrid {
+    /// The number printed in front of each row and how many bubbles it has.
+    rows: Vec<(usize, usize)>,
+    start_char: char,
+    /// Rows already rendered on previous pages.
+    rendered: usize,
+}
+impl BubbleGrid {
+    pub fn new(rows: Vec<(usize, usize)>, start_char: char) -> Self {
+        Self {
+            rows,
+            start_char,
+            rendered: 0,
+        }
+    }
+}
+impl Element for BubbleGrid {
+    fn render(
+        &mut self,
+        context: &Context,
+        area: render::Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, Error> {
+        let mut result = RenderResult::default();
+
+        let spacing = Mm::from(BUBBLE_SPACING);
+        let bubbles = self.rows.iter().map(|r| r.1).max().unwrap_or(0);
+        let label_width = spacing * 1.5;
+        let column_width = label_width + spacing * (bubbles as f64 + 1.0);
+
+        let columns = (f64::from(area.size().width) / f64::from(column_width)).max(1.0) as usize;
+        let rows_per_page = (f64::from(area.size().height) / f64::from(spacing)) as usize;
+        if rows_per_page == 0 {
+            result.has_more = true;
+            return Ok(result);
+        }
+
+        // Fill the columns top to bottom so the numbers read down like the questions do
+        let remaining = &self.rows[self.rendered..];
+        let count = remaining.len().min(rows_per_page * columns);
+        let rows_here = count.div_ceil(columns);
+        let number_style = style.bold();
+        for (i, (number, answers)) in remaining[..count].iter().enumerate() {
+            let x = column_width * (i / rows_here) as f64;
+            let y = spacing * ((i % rows_here) as f64 + 0.5);
+
+            let label = format!("{}.", number);
+            let label_x = x + label_width - style.str_width(&context.font_cache, &label);
+            let metrics = style.metrics(&context.font_cache);
+            area.print_str(
+                &context.font_cache,
+                Position::new(label_x, y - metrics.ascent * 0.7),
+                number_style,
+                &label,
+            )?;
+
+            for answer in 0..*answers {
+                let letter = AlphabeticOrderedList::letter(self.start_char, answer as u32);
+                let center = Position::new(x + label_width + spacing * (answer as f64 + 0.75), y);
+                draw_bubble(&area, context, style, center, &letter.to_string())?;
+            }
+        }
+
+        self.rendered += count;
+        result.has_more = self.rendered < self.rows.len();
+        result.size = Size::new(area.size().width, spacing * rows_here as f64);
+        Ok(result)
+    }
 }
@@ -1,172 +1,344 @@
-use std::time::{Duration, Instant};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use ilog::IntLog;
-use rand::seq::SliceRandom;
+use anyhow::Context;
+use image::DynamicImage;
+use rand::Rng;
 use rckive_genpdf::{
-    elements::{Break, PaddedElement, Paragraph, Text},
-    style::{Style, StyledString},
-    Document, Element, Margins,
+    elements::{
+        Break, FrameCellDecorator, Image, LinearLayout, PaddedElement, PageBreak, Paragraph,
+        TableLayout,
+    },
+    fonts::{Font, FontFamily},
+    style::{Color, Style, StyledString},
+    Alignment, Document, Element, Margins,
 };
 
 use crate::{
-    data::{Project, Question},
-    pdf_elements::{AlphabeticOrderedList, CharRepeat, SplitElement},
+    data::{ImageAlignment, Project, Question, QuestionImage},
+    layout,
+    markup::{self, Block, Inline},
+    paper::Paper,
+    pdf_elements::{
+        AlphabeticOrderedList, AnswerBox, Boxed, BubbleGrid, CharRepeat, CodeBlock, Formula,
+        IdBubbles, RegistrationMarks, RichSpan, RichText, SplitElement, TickBoxes,
+    },
 };
 
-fn gen_points_element(i: usize, question: &Question, project: &Project) -> impl Element {
-    let show_hint = if let Question::Selection(q) = question {
-        q.correct.len() >= 2 && project.settings.show_hints
-    } else {
-        false
-    };
-
-    let language = &project.settings.language;
-
-    let title = if show_hint {
-        format!(
-            "{}. {} ({})",
-            i + 1,
-            question.get_title(),
-            language.multiple_answers_hint()
-        )
-    } else {
-        format!("{}. {}", i + 1, question.get_title())
-    };
+/// Font families loaded into a document next to its default one. They are only loaded when the
+/// project uses them, so other projects don't need the font files.
+#[derive(Clone, Copy)]
+struct Fonts {
+    code: Option<FontFamily<Font>>,
+    math: Option<FontFamily<Font>>,
+}
+impl Fonts {
+    fn code_style(&self) -> Style {
+        let style = Style::new().with_font_size(10);
+        match self.code {
+            Some(family) => style.with_font_family(family),
+            None => style,
+        }
+    }
 
-    let mut points_element =
-        Paragraph::new(format!("{}", language.format_points(question.get_points())));
-    points_element.set_alignment(rckive_genpdf::Alignment::Right);
+    fn math_style(&self) -> Style {
+        match self.math {
+            Some(family) => Style::new().with_font_family(family),
+            None => Style::new(),
+        }
+    }
+}
 
-    SplitElement::new(Paragraph::new(title), points_element, 0.9)
+/// Whether `check` holds for the text or any of the answers of a question in the project.
+fn any_text(project: &Project, check: fn(&str) -> bool) -> bool {
+    project.questions.iter().any(|q| {
+        let answers: Vec<&str> = match q {
+            Question::Selection(q) => q
+                .correct
+                .iter()
+                .chain(q.incorrect.iter())
+                .map(|a| a.text.as_str())
+                .collect(),
+            Question::Matching(q) => q
+                .pairs
+                .iter()
+                .flat_map(|p| [p.term.as_str(), p.definition.as_str()])
+                .collect(),
+            Question::Ordering(q) => q.items.iter().map(String::as_str).collect(),
+            _ => vec![],
+        };
+        check(&q.get_title()) || answers.into_iter().any(check)
+    })
 }
 
-fn gen_header(doc: &mut Document, project: &Project) {
-    let header = &project.header;
-    let language = &project.settings.language;
+/// Appends a line of text with inline markup to `text`, on top of `style`.
+fn push_markup(text: &mut RichText, line: &str, style: Style, fonts: Fonts) -> anyhow::Result<()> {
+    for inline in markup::parse_inline(line)? {
+        let span = match inline {
+            Inline::Text(span) => span,
+            Inline::Math(node) => {
+                text.push_formula(Formula::new(node), style.and(fonts.math_style()));
+                continue;
+            }
+        };
 
-    let mut title = Paragraph::new(StyledString::new(
-        &header.title,
-        Style::new().with_font_size(18),
-    ));
-    title.set_alignment(rckive_genpdf::Alignment::Center);
-    doc.push(title);
+        let mut span_style = style;
+        if span.bold {
+            span_style = span_style.bold();
+        }
+        if span.italic {
+            span_style = span_style.italic();
+        }
+        text.push(RichSpan {
+            text: StyledString::new(span.text, span_style),
+            underline: span.underline,
+            script: span.script,
+        });
+    }
+    Ok(())
+}
 
-    doc.push(Break::new(1.0));
+fn rich_text(line: &str, style: Style, fonts: Fonts) -> anyhow::Result<RichText> {
+    let mut text = RichText::new();
+    push_markup(&mut text, line, style, fonts)?;
+    Ok(text)
+}
 
-    // TODO: export to an Element that requires a string and repeats a char until the end of the
-    // area
-    let name = Paragraph::new(StyledString::new(
-        format!(
-            "{}: ________________________________________",
-            language.input_name()
-        ),
-        Style::new().with_font_size(14),
-    ));
+/// Pushes a line of text, a formula or a code block of a question or answer.
+fn push_block(
+    layout: &mut LinearLayout,
+    block: Block,
+    style: Style,
+    project: &Project,
+    fonts: Fonts,
+) -> anyhow::Result<()> {
+    match block {
+        Block::Text(line) => layout.push(rich_text(line, style, fonts)?),
+        Block::Math(node) => layout.push(Formula::new(node).styled(style.and(fonts.math_style()))),
+        Block::Code(lines) => {
+            let settings = &project.settings;
+            let code = CodeBlock::new(&lines, settings.code_line_numbers, settings.code_frame);
+            layout.push(PaddedElement::new(
+                code.styled(style.and(fonts.code_style())),
+                Margins::vh(1.0, 0.0),
+            ));
+        }
+    }
+    Ok(())
+}
 
-    let class = SplitElement::new(
-        Paragraph::new(StyledString::new(
-            format!("{}: _____", language.input_class()),
-            Style::new().with_font_size(14),
-        )),
-        Paragraph::new(StyledString::new(
-            format!("{}: _____", language.input_class_num()),
-            Style::new().with_font_size(14),
-        )),
-        0.5,
-    );
+/// Loads an image for the paper, scaled to the width set in the project.
+fn gen_image(image: &QuestionImage) -> anyhow::Result<Image> {
+    let data =
+        image::open(&image.path).with_context(|| format!("Could not open image {}", image.path))?;
 
-    doc.push(SplitElement::new(name, class, 0.7));
+    // Images with an alpha channel can't be embedded, so put them on a white background
+    let rgba = data.to_rgba8();
+    let rgb = image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u16 * a as u16 + 255 * (255 - a as u16)) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    });
 
-    doc.push(Break::new(0.5));
+    let mut element = Image::from_dynamic_image(DynamicImage::ImageRgb8(rgb))?;
+    // rckive-genpdf panics when rendering an image without rotation, so give it an invisible one
+    element.set_clockwise_rotation(1e-6);
+    element.set_alignment(match image.alignment {
+        ImageAlignment::Left => Alignment::Left,
+        ImageAlignment::Center => Alignment::Center,
+        ImageAlignment::Right => Alignment::Right,
+    });
+    if let Some(width) = image.width {
+        element.set_dpi(rgba.width() as f64 * 25.4 / width.max(1.0));
+    }
+    Ok(element)
 }
 
-fn gen_questions(doc: &mut Document, project: &Project) -> usize {
-    doc.set_font_size(12);
-    let mut rng = rand::thread_rng();
-    let mut points: usize = 0;
-    let language = project.settings.language.clone();
+/// A small table of the points needed for each grade, see `layout::Element::GradingTable`.
+fn gen_grading_table(columns: &[[String; 2]]) -> anyhow::Result<TableLayout> {
+    let style = Style::new().with_font_size(10);
+    let cell = |text: &str, style: Style| {
+        let mut cell = Paragraph::new(StyledString::new(text, style));
+        cell.set_alignment(Alignment::Center);
+        PaddedElement::new(cell, Margins::vh(0.5, 1.0))
+    };
+
+    let mut table = TableLayout::new(vec![2; columns.len()]);
+    table.set_cell_decorator(FrameCellDecorator::new(true, true, false));
 
-    let mut questions = project.questions.clone();
-    if project.settings.randomize_questions {
-        questions.shuffle(&mut rng);
+    // The first column names the rows
+    for row in 0..2 {
+        let mut cells = table.row();
+        for (n, column) in columns.iter().enumerate() {
+            let style = if n == 0 || row == 0 {
+                style.bold()
+            } else {
+                style
+            };
+            cells.push_element(cell(&column[row], style));
+        }
+        cells.push()?;
     }
 
-    for (i, question) in questions
-        .iter()
-        .take(project.settings.max_questions as usize)
-        .enumerate()
-    {
-        doc.push(gen_points_element(i, question, &project));
-        points += question.get_points() as usize;
+    Ok(table)
+}
 
-        match question {
-            Question::Selection(question) => {
-                let mut questions: Vec<String> = question.correct.clone().into_iter().collect();
-                questions.append(&mut question.incorrect.clone().into_iter().collect());
-                questions.shuffle(&mut rng);
+fn gen_style(style: layout::Style) -> Style {
+    let mut result = Style::new().with_font_size(style.size);
+    if style.bold {
+        result = result.bold();
+    }
+    if style.italic {
+        result = result.italic();
+    }
+    if style.grey {
+        result = result.with_color(Color::Greyscale(128));
+    }
+    result
+}
 
-                let mut list = AlphabeticOrderedList::new(language.get_first_char());
-                for answer in questions {
-                    list.push(Text::new(answer))
-                }
-                doc.push(list);
-            }
-            Question::Input(question) => {
-                doc.push(Break::new(0.5));
-                for _ in 0..question.number_of_lines {
-                    #[rustfmt::skip]
-                    doc.push(PaddedElement::new(
-                        CharRepeat::new('.'),
-                        Margins::vh(1.5, 0.0)
-                    ));
-                }
+fn gen_text(text: &layout::Text, fonts: Fonts) -> anyhow::Result<Box<dyn Element>> {
+    if !text.is_plain() {
+        let mut rich = RichText::new();
+        for span in text.spans.iter() {
+            let style = gen_style(span.style);
+            match span.markup {
+                true => push_markup(&mut rich, &span.text, style, fonts)?,
+                false => rich.push_styled(span.text.clone(), style),
             }
         }
-
-        doc.push(Break::new(1));
+        return Ok(Box::new(rich));
     }
 
-    points
+    let mut paragraph = Paragraph::default();
+    for span in text.spans.iter() {
+        paragraph.push_styled(span.text.clone(), gen_style(span.style));
+    }
+    paragraph.set_alignment(match text.align {
+        layout::Align::Left => Alignment::Left,
+        layout::Align::Center => Alignment::Center,
+        layout::Align::Right => Alignment::Right,
+    });
+    Ok(Box::new(paragraph))
 }
 
-fn gen_footer(doc: &mut Document, project: &Project, max_points: usize) {
-    let poins_needed_space = max_points.log10() + 2;
-
-    let examiner = SplitElement::new(
-        Paragraph::new(format!("{}: ", project.settings.language.get_examiner())),
-        Paragraph::new("______________________________"),
-        0.0,
-    );
-    let points = Paragraph::new(format!(
-        "{}: {}/{}",
-        project.settings.language.get_points_sum(),
-        "_".repeat(poins_needed_space as usize),
-        max_points
-    ));
-
-    doc.push(SplitElement::new(examiner, points, 0.7));
+/// Turns an element of the layout into one genpdf can render.
+fn gen_element(
+    element: &layout::Element,
+    project: &Project,
+    fonts: Fonts,
+) -> anyhow::Result<Boxed> {
+    let gen = |element| gen_element(element, project, fonts);
+    let element: Box<dyn Element> = match element {
+        layout::Element::Text(text) => gen_text(text, fonts)?,
+        layout::Element::Blocks(text, style) => {
+            let mut layout = LinearLayout::vertical();
+            for block in markup::parse_blocks(text)? {
+                push_block(&mut layout, block, gen_style(*style), project, fonts)?;
+            }
+            Box::new(layout)
+        }
+        layout::Element::Image(image) => Box::new(gen_image(image)?),
+        layout::Element::Split(left, right, share) => {
+            Box::new(SplitElement::new(gen(left)?, gen(right)?, *share))
+        }
+        layout::Element::Column(elements) => {
+            let mut layout = LinearLayout::vertical();
+            for element in elements {
+                layout.push(gen(element)?);
+            }
+            Box::new(layout)
+        }
+        layout::Element::Padded(element, margins) => Box::new(PaddedElement::new(
+            gen(element)?,
+            Margins::trbl(margins.top, margins.right, margins.bottom, margins.left),
+        )),
+        layout::Element::List(first_char, items) => {
+            let mut list = AlphabeticOrderedList::new(*first_char);
+            for item in items {
+                list.push(gen(item)?);
+            }
+            Box::new(list)
+        }
+        layout::Element::Break(lines) => Box::new(Break::new(*lines)),
+        layout::Element::PageBreak => Box::new(PageBreak::new()),
+        layout::Element::WritingLine => Box::new(CharRepeat::new('.')),
+        layout::Element::AnswerBox(width) => Box::new(AnswerBox::new(*width)),
+        layout::Element::TickBoxes(labels) => Box::new(TickBoxes::new(labels.clone())),
+        layout::Element::GradingTable(columns) => Box::new(gen_grading_table(columns)?),
+        layout::Element::RegistrationMarks(size) => Box::new(RegistrationMarks::new(*size)),
+        layout::Element::IdBubbles(digits) => Box::new(IdBubbles::new(*digits)),
+        layout::Element::BubbleGrid(rows, first_char) => {
+            Box::new(BubbleGrid::new(rows.clone(), *first_char))
+        }
+    };
+    Ok(Boxed(element))
 }
 
-pub fn generate_pdf(project: &Project) -> anyhow::Result<Duration> {
-    let start = Instant::now();
-
-    let font_family = rckive_genpdf::fonts::from_files(
-        &project.settings.fonts_path,
-        &project.settings.font,
-        None,
-    )?;
+fn new_document(project: &Project) -> anyhow::Result<(Document, Fonts)> {
+    let settings = &project.settings;
+    let font_family = rckive_genpdf::fonts::from_files(&settings.fonts_path, &settings.font, None)?;
 
     let mut doc = rckive_genpdf::Document::new(font_family);
+    let mut load = |used: bool, name: &str| -> anyhow::Result<Option<FontFamily<Font>>> {
+        if !used {
+            return Ok(None);
+        }
+        let family = rckive_genpdf::fonts::from_files(&settings.fonts_path, name, None)
+            .with_context(|| format!("Could not load the font {}", name))?;
+        Ok(Some(doc.add_font_family(family)))
+    };
+    let fonts = Fonts {
+        code: load(any_text(project, markup::has_code), &settings.code_font)?,
+        math: load(any_text(project, markup::has_math), &settings.math_font)?,
+    };
     doc.set_paper_size(project.settings.paper_size);
     doc.set_title(&project.header.title);
 
     let mut decorator = rckive_genpdf::SimplePageDecorator::new();
-    decorator.set_margins(10);
+    decorator.set_margins(layout::MARGIN);
     doc.set_page_decorator(decorator);
 
-    gen_header(&mut doc, &project);
-    let max_points = gen_questions(&mut doc, &project);
-    gen_footer(&mut doc, &project, max_points);
+    Ok((doc, fonts))
+}
+
+/// Appends `suffix` to the file stem of `output`, e.g. `output.pdf` -> `output_key.pdf`.
+fn path_with_suffix(output: &Path, suffix: &str) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match output.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+
+    output.with_file_name(file_name)
+}
+
+/// Returns the path of the paper for `variant`, e.g. `output_A.pdf`.
+pub fn variant_path(output: &str, variant: Option<char>) -> PathBuf {
+    match variant {
+        Some(variant) => path_with_suffix(Path::new(output), &variant.to_string()),
+        None => PathBuf::from(output),
+    }
+}
+
+/// Returns the path of the answer key written next to `paper_path`, e.g. `output_key.pdf`.
+pub fn key_path(paper_path: &Path) -> PathBuf {
+    path_with_suffix(paper_path, "key")
+}
+
+/// Renders `paper` and its answer key, returning the paths of both files.
+fn render_paper(project: &Project, paper: &Paper) -> anyhow::Result<[PathBuf; 2]> {
+    let path = variant_path(&project.settings.output, paper.variant);
+
+    let (mut doc, fonts) = new_document(project)?;
+    for element in layout::paper(project, paper, Some(paper.seed)).iter() {
+        doc.push(gen_element(element, project, fonts)?);
+    }
     // TODO: Docs:
     // Разработка на софтуер - генерално
     // Agile, SCRUM
@@ -175,7 +347,41 @@ pub fn generate_pdf(project: &Project) -> anyhow::Result<Duration> {
     // Изисквания
     // Подобни проекти
 
-    doc.render_to_file(&project.settings.output)?;
+    doc.render_to_file(&path)?;
+
+    let (mut key, fonts) = new_document(project)?;
+    for element in layout::key(project, paper).iter() {
+        key.push(gen_element(element, project, fonts)?);
+    }
+    let key_path = key_path(&path);
+    key.render_to_file(&key_path)?;
+
+    Ok([path, key_path])
+}
+
+/// Generates every variant with its answer key, returning the written files and how long it took.
+pub fn generate_pdf(project: &Project) -> anyhow::Result<(Vec<PathBuf>, Duration)> {
+    let start = Instant::now();
+    project.validate()?;
+
+    let variants = project.settings.variants;
+    if !(1..=26).contains(&variants) {
+        anyhow::bail!(
+            "Number of variants must be between 1 and 26, got {}",
+            variants
+        );
+    }
+
+    let seed = project
+        .settings
+        .seed
+        .unwrap_or_else(|| rand::thread_rng().gen::<u32>().into());
+    let mut paths = vec![];
+    for i in 0..variants {
+        let variant = (variants > 1).then_some((b'A' + i) as char);
+        let paper = Paper::new(project, variant, seed.wrapping_add(i.into()))?;
+        paths.extend(render_paper(project, &paper)?);
+    }
 
-    Ok(start.elapsed())
+    Ok((paths, start.elapsed()))
 }
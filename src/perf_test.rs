@@ -2,9 +2,9 @@ use std::time::Duration;
 
 use crate::{data::Project, pdf_gen};
 
-fn print_stats(times: &mut Vec<Duration>) {
+fn print_stats(times: &mut [Duration]) {
     times.sort();
-    let median = if times.len() % 2 == 0 {
+    let median = if times.len().is_multiple_of(2) {
         let a = times[times.len() / 2 - 1];
         let b = times[times.len() / 2];
         a + b
@@ -25,7 +25,7 @@ fn test_pdf_generation(project: &Project) -> anyhow::Result<()> {
 
     println!("Testing PDF generation {} times", TEST_ITER);
     for _ in 0..TEST_ITER {
-        times.push(pdf_gen::generate_pdf(&project)?);
+        times.push(pdf_gen::generate_pdf(project)?.1);
     }
 
     print_stats(&mut times);
@@ -0,0 +1,600 @@
+//! A preview of the paper drawn with egui, so it can follow every edit without rendering a PDF.
+//! It draws the elements from `layout` that the PDF is printed from, but text is laid out with
+//! egui's own fonts, so line and page breaks only approximate the PDF.
+
+use std::{collections::HashMap, sync::Arc};
+
+use egui::{
+    pos2,
+    text::{LayoutJob, TextFormat},
+    vec2, Align, Color32, ColorImage, Context, FontId, Galley, Pos2, Rect, Shape, Stroke,
+    TextureHandle, Ui, Vec2,
+};
+
+use crate::{
+    data::{ImageAlignment, Project, QuestionImage},
+    layout::{self, Element, Style, Text, FONT_SIZE, MARGIN},
+    markup::{self, Block, Inline, Script},
+    math::MathNode,
+    paper::Paper,
+    pdf_elements::{AlphabeticOrderedList, BUBBLE_RADIUS, BUBBLE_SPACING},
+    settings::Settings,
+};
+
+const MM_PER_PT: f32 = 25.4 / 72.0;
+/// Images are shown no bigger than this many pixels on either side.
+const IMAGE_SIZE: u32 = 1024;
+/// How far genpdf's `BulletPoint` moves list items right.
+const BULLET_INDENT: f32 = 10.0;
+
+/// The size of a page of the project in millimeters.
+pub fn page_size(project: &Project) -> Vec2 {
+    let (width, height) = project.settings.paper_size.dimensions();
+    vec2(width, height)
+}
+
+/// Loaded images by path, `None` when the file could not be loaded.
+pub type Textures = HashMap<String, Option<TextureHandle>>;
+
+/// Loads the image at `path` into a texture at most `size` pixels on either side, or takes it from
+/// `textures` when it was loaded before.
+pub fn load_texture(
+    textures: &mut Textures,
+    path: &str,
+    size: u32,
+    ctx: &Context,
+) -> Option<TextureHandle> {
+    let texture = textures.entry(path.to_string()).or_insert_with(|| {
+        let image = image::open(path).ok()?.thumbnail(size, size).to_rgba8();
+        let dimensions = [image.width() as usize, image.height() as usize];
+        let image = ColorImage::from_rgba_unmultiplied(dimensions, image.as_raw());
+        Some(ctx.load_texture(path, image, Default::default()))
+    });
+    texture.clone()
+}
+
+/// The pages of the preview, kept until the project or the size of the preview changes. Every
+/// page is a list of shapes relative to its top left corner.
+#[derive(Clone)]
+pub struct Cache {
+    revision: u64,
+    settings: Settings,
+    scale: f32,
+    pixels_per_point: f32,
+    pub pages: Result<Vec<Vec<Shape>>, String>,
+}
+impl Cache {
+    /// Lays out the first variant of the project, `scale` pixels to a millimeter. Its images are
+    /// loaded into `textures`, which outlive the cache so they aren't read again on every edit.
+    pub fn new(project: &Project, textures: &mut Textures, ui: &Ui, scale: f32) -> Self {
+        let variant = (project.settings.variants > 1).then_some('A');
+        // Without a seed any order will do, the paper says it is random
+        let seed = project.settings.seed;
+        let pages = Paper::new(project, variant, seed.unwrap_or(0))
+            .map(|paper| layout::paper(project, &paper, seed.map(|_| paper.seed)))
+            .map(|elements| {
+                let mut pages = Pages::new(ui, textures, scale, page_size(project));
+                for element in elements.iter() {
+                    pages.element(element, 0.0, pages.width);
+                }
+                pages.pages
+            })
+            .map_err(|err| format!("{:#}", err));
+
+        Self {
+            revision: project.gui_state.history.revision(),
+            settings: project.settings.clone(),
+            scale,
+            pixels_per_point: ui.ctx().pixels_per_point(),
+            pages,
+        }
+    }
+
+    /// Whether the pages still show `project` as it would be drawn at `scale`.
+    pub fn is_for(&self, project: &Project, ui: &Ui, scale: f32) -> bool {
+        self.revision == project.gui_state.history.revision()
+            && self.settings == project.settings
+            && self.scale == scale
+            && self.pixels_per_point == ui.ctx().pixels_per_point()
+    }
+}
+
+struct Pages<'a> {
+    ui: &'a Ui,
+    textures: &'a mut Textures,
+    /// Pixels in a millimeter.
+    scale: f32,
+    /// Size of the area inside the margins, in millimeters.
+    width: f32,
+    height: f32,
+    /// The page the next element goes on and how far down it.
+    page: usize,
+    y: f32,
+    pages: Vec<Vec<Shape>>,
+}
+impl<'a> Pages<'a> {
+    fn new(ui: &'a Ui, textures: &'a mut Textures, scale: f32, page: Vec2) -> Self {
+        let margin = MARGIN as f32;
+        Self {
+            ui,
+            textures,
+            scale,
+            width: page.x - 2.0 * margin,
+            height: page.y - 2.0 * margin,
+            page: 0,
+            y: 0.0,
+            pages: vec![vec![]],
+        }
+    }
+
+    fn format(&self, style: Style) -> TextFormat {
+        TextFormat {
+            font_id: FontId::proportional(f32::from(style.size) * MM_PER_PT * self.scale),
+            color: match style.grey {
+                true => Color32::GRAY,
+                false => Color32::BLACK,
+            },
+            italics: style.italic,
+            ..Default::default()
+        }
+    }
+
+    fn plain(&self, text: &str, style: Style) -> LayoutJob {
+        let mut job = LayoutJob::default();
+        job.append(text, 0.0, self.format(style));
+        job
+    }
+
+    fn job(&self, text: &Text) -> LayoutJob {
+        let mut job = LayoutJob::default();
+        for span in text.spans.iter() {
+            match span.markup {
+                true => self.append_markup(&mut job, &span.text, span.style),
+                false => job.append(&span.text, 0.0, self.format(span.style)),
+            }
+        }
+        job
+    }
+
+    /// Appends a line with its inline markup. Lines with mistakes are shown as written.
+    fn append_markup(&self, job: &mut LayoutJob, line: &str, style: Style) {
+        let Ok(inlines) = markup::parse_inline(line) else {
+            job.append(line, 0.0, self.format(style));
+            return;
+        };
+
+        let size = f32::from(style.size);
+        for inline in inlines {
+            match inline {
+                Inline::Text(span) => {
+                    let (size, valign) = match span.script {
+                        Script::Normal => (size, Align::BOTTOM),
+                        Script::Sub => (size * 0.7, Align::BOTTOM),
+                        Script::Super => (size * 0.7, Align::TOP),
+                    };
+                    let mut format = self.format(Style {
+                        italic: style.italic || span.italic,
+                        ..style
+                    });
+                    format.font_id.size = size * MM_PER_PT * self.scale;
+                    format.valign = valign;
+                    if span.underline {
+                        format.underline = Stroke::new(1.0, Color32::BLACK);
+                    }
+                    job.append(&span.text, 0.0, format);
+                }
+                Inline::Math(node) => self.math(job, &node, size, Align::BOTTOM),
+            }
+        }
+    }
+
+    /// Writes a formula on one line, with fractions as `a/b` and scripts raised or lowered.
+    fn math(&self, job: &mut LayoutJob, node: &MathNode, size: f32, valign: Align) {
+        let mut append = |text: &str, italics: bool| {
+            let mut format = self.format(Style::default());
+            format.font_id.size = size * MM_PER_PT * self.scale;
+            format.italics = italics;
+            format.valign = valign;
+            job.append(text, 0.0, format);
+        };
+
+        match node {
+            MathNode::Symbol {
+                text,
+                italic,
+                spaced,
+            } => match spaced {
+                true => append(&format!(" {} ", text), *italic),
+                false => append(text, *italic),
+            },
+            MathNode::Row(nodes) => {
+                for node in nodes {
+                    self.math(job, node, size, valign);
+                }
+            }
+            MathNode::Frac(numerator, denominator) => {
+                self.math_group(job, numerator, size, valign);
+                self.math(job, &symbol("/"), size, valign);
+                self.math_group(job, denominator, size, valign);
+            }
+            MathNode::Sqrt(radicand) => {
+                append("√", false);
+                self.math_group(job, radicand, size, valign);
+            }
+            MathNode::Scripts { base, sub, sup } => {
+                self.math(job, base, size, valign);
+                if let Some(sub) = sub {
+                    self.math(job, sub, size * 0.7, Align::BOTTOM);
+                }
+                if let Some(sup) = sup {
+                    self.math(job, sup, size * 0.7, Align::TOP);
+                }
+            }
+        }
+    }
+
+    /// Writes a formula in parentheses when it is more than one symbol.
+    fn math_group(&self, job: &mut LayoutJob, node: &MathNode, size: f32, valign: Align) {
+        let parens = matches!(node, MathNode::Row(_) | MathNode::Frac(..));
+        if parens {
+            self.math(job, &symbol("("), size, valign);
+        }
+        self.math(job, node, size, valign);
+        if parens {
+            self.math(job, &symbol(")"), size, valign);
+        }
+    }
+
+    fn galley(&self, mut job: LayoutJob, width: f32) -> Arc<Galley> {
+        job.wrap.max_width = width * self.scale;
+        self.ui.fonts(|fonts| fonts.layout_job(job))
+    }
+
+    /// Size of laid out text in millimeters.
+    fn size_of(&self, galley: &Galley) -> Vec2 {
+        galley.size() / self.scale
+    }
+
+    fn width_of(&self, job: LayoutJob) -> f32 {
+        self.size_of(&self.galley(job, f32::INFINITY)).x
+    }
+
+    fn line_height(&self, size: u8) -> f32 {
+        let job = self.plain("X", Style::sized(size));
+        self.size_of(&self.galley(job, f32::INFINITY)).y
+    }
+
+    fn pos(&self, x: f32, y: f32) -> Pos2 {
+        let margin = MARGIN as f32;
+        pos2((margin + x) * self.scale, (margin + y) * self.scale)
+    }
+
+    fn add(&mut self, shape: Shape) {
+        self.pages[self.page].push(shape);
+    }
+
+    fn new_page(&mut self) {
+        self.page += 1;
+        if self.page == self.pages.len() {
+            self.pages.push(vec![]);
+        }
+        self.y = 0.0;
+    }
+
+    /// Takes `height` millimeters of the page, moving to a new page when they don't fit, and
+    /// returns where they start.
+    fn reserve(&mut self, height: f32) -> f32 {
+        if self.y > 0.0 && self.y + height > self.height {
+            self.new_page();
+        }
+        let y = self.y;
+        self.y += height;
+        y
+    }
+
+    /// Leaves empty space, measured in lines of regular text like genpdf's `Break`.
+    fn skip(&mut self, lines: f64) {
+        self.y += lines as f32 * self.line_height(FONT_SIZE);
+    }
+
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let rect = Rect::from_min_size(self.pos(x, y), vec2(width, height) * self.scale);
+        self.add(Shape::rect_stroke(
+            rect,
+            0.0,
+            Stroke::new(1.0, Color32::BLACK),
+        ));
+    }
+
+    /// Places text in `width` millimeters from `x` and returns where it starts.
+    fn text(&mut self, job: LayoutJob, x: f32, width: f32, align: layout::Align) -> f32 {
+        let galley = self.galley(job, width);
+        let size = self.size_of(&galley);
+        let y = self.reserve(size.y);
+        let x = match align {
+            layout::Align::Left => x,
+            layout::Align::Center => x + (width - size.x) / 2.0,
+            layout::Align::Right => x + width - size.x,
+        };
+        self.add(Shape::galley(self.pos(x, y), galley));
+        y
+    }
+
+    /// How wide an element is when it is only given the width it needs, like the left side of a
+    /// split with a share of 0.
+    fn natural_width(&self, element: &Element) -> f32 {
+        match element {
+            Element::Text(text) => self.width_of(self.job(text)),
+            Element::Split(left, right, _) => self.natural_width(left) + self.natural_width(right),
+            Element::AnswerBox(width) => {
+                *width as f32 + self.width_of(self.plain(" ", Style::default()))
+            }
+            _ => f32::INFINITY,
+        }
+    }
+
+    /// Draws `element` in `width` millimeters from `x`, below what was drawn before it.
+    fn element(&mut self, element: &Element, x: f32, width: f32) {
+        match element {
+            Element::Text(text) => {
+                self.text(self.job(text), x, width, text.align);
+            }
+            Element::Blocks(text, style) => self.blocks(text, *style, x, width),
+            Element::Image(image) => self.image(image, x, width),
+            Element::Split(left, right, share) => {
+                let left_width = match *share == 0.0 {
+                    true => self.natural_width(left).min(width),
+                    false => width * *share as f32,
+                };
+                let start = (self.page, self.y);
+                self.element(left, x, left_width);
+                let end = (self.page, self.y);
+
+                // The taller side decides where the next element goes
+                (self.page, self.y) = start;
+                self.element(right, x + left_width, width - left_width);
+                if end > (self.page, self.y) {
+                    (self.page, self.y) = end;
+                }
+            }
+            Element::Column(elements) => {
+                for element in elements {
+                    self.element(element, x, width);
+                }
+            }
+            Element::Padded(element, margins) => {
+                let (left, right) = (margins.left as f32, margins.right as f32);
+                self.y += margins.top as f32;
+                self.element(element, x + left, width - left - right);
+                self.y += margins.bottom as f32;
+            }
+            Element::List(first_char, items) => {
+                for (pos, item) in items.iter().enumerate() {
+                    // The letter goes right aligned before the item like genpdf's `BulletPoint`
+                    let letter = AlphabeticOrderedList::letter(*first_char, pos as u32);
+                    let bullet = self.galley(
+                        self.plain(&format!("{})", letter), Style::default()),
+                        f32::INFINITY,
+                    );
+                    let size = self.size_of(&bullet);
+                    let y = self.reserve(size.y);
+                    let bullet_x = x + BULLET_INDENT - 2.0 - size.x;
+                    self.add(Shape::galley(self.pos(bullet_x, y), bullet));
+
+                    self.y = y;
+                    self.element(item, x + BULLET_INDENT, width - BULLET_INDENT);
+                }
+            }
+            Element::Break(lines) => self.skip(*lines),
+            Element::PageBreak => self.new_page(),
+            Element::WritingLine => {
+                let line_height = self.line_height(FONT_SIZE);
+                let y = self.reserve(line_height) + line_height;
+                let line = Shape::dashed_line(
+                    &[self.pos(x, y), self.pos(x + width, y)],
+                    Stroke::new(1.0, Color32::GRAY),
+                    0.5 * self.scale,
+                    1.0 * self.scale,
+                );
+                for shape in line {
+                    self.add(shape);
+                }
+            }
+            Element::AnswerBox(box_width) => {
+                let line_height = self.line_height(FONT_SIZE);
+                let y = self.reserve(line_height);
+                self.rect(x, y, *box_width as f32, line_height);
+            }
+            Element::TickBoxes(labels) => self.tick_boxes(labels, x),
+            Element::GradingTable(columns) => self.grading_table(columns, x, width),
+            Element::RegistrationMarks(size) => {
+                // They take up no space, like in the PDF
+                let size = *size as f32;
+                for (mark_x, mark_y) in [
+                    (x, 0.0),
+                    (x + width - size, 0.0),
+                    (x, self.height - size),
+                    (x + width - size, self.height - size),
+                ] {
+                    let rect = Rect::from_min_size(
+                        self.pos(mark_x, mark_y),
+                        vec2(size, size) * self.scale,
+                    );
+                    self.add(Shape::rect_filled(rect, 0.0, Color32::BLACK));
+                }
+            }
+            Element::IdBubbles(digits) => self.id_bubbles(*digits, x),
+            Element::BubbleGrid(rows, first_char) => self.bubble_grid(rows, *first_char, x),
+        }
+    }
+
+    fn block(&mut self, block: &Block, style: Style, x: f32, width: f32) {
+        match block {
+            Block::Text(line) => {
+                let mut job = LayoutJob::default();
+                self.append_markup(&mut job, line, style);
+                self.text(job, x, width, layout::Align::Left);
+            }
+            Block::Code(lines) => {
+                let mut format = self.format(Style::sized(10));
+                format.font_id.family = egui::FontFamily::Monospace;
+                format.background = Color32::from_gray(235);
+                let mut job = LayoutJob::default();
+                job.append(&lines.join("\n"), 0.0, format);
+                self.y += 1.0;
+                self.text(job, x, width, layout::Align::Left);
+                self.y += 1.0;
+            }
+            Block::Math(node) => {
+                let mut job = LayoutJob::default();
+                self.math(&mut job, node, f32::from(style.size), Align::BOTTOM);
+                self.text(job, x, width, layout::Align::Center);
+            }
+        }
+    }
+
+    fn blocks(&mut self, text: &str, style: Style, x: f32, width: f32) {
+        match markup::parse_blocks(text) {
+            Ok(blocks) => {
+                for block in blocks.iter() {
+                    self.block(block, style, x, width);
+                }
+            }
+            Err(_) => {
+                self.text(self.plain(text, style), x, width, layout::Align::Left);
+            }
+        }
+    }
+
+    /// The image as big as it will be printed, or a grey box with its path when it can't be
+    /// loaded.
+    fn image(&mut self, image: &QuestionImage, x: f32, available: f32) {
+        let (width, height) = match image::image_dimensions(&image.path) {
+            // genpdf prints images at 300 dpi unless they are given a width
+            Ok((w, h)) => {
+                let width = image.width.map_or(w as f32 * 25.4 / 300.0, |w| w as f32);
+                (width, width * h as f32 / w.max(1) as f32)
+            }
+            Err(_) => {
+                let width = image.width.map_or(40.0, |w| w as f32);
+                (width, width * 0.75)
+            }
+        };
+        let (width, height) = match width > available {
+            true => (available, height * available / width),
+            false => (width, height),
+        };
+        let x = match image.alignment {
+            ImageAlignment::Left => x,
+            ImageAlignment::Center => x + (available - width) / 2.0,
+            ImageAlignment::Right => x + available - width,
+        };
+
+        let y = self.reserve(height);
+        let rect = Rect::from_min_size(self.pos(x, y), vec2(width, height) * self.scale);
+        if let Some(texture) = load_texture(self.textures, &image.path, IMAGE_SIZE, self.ui.ctx()) {
+            let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+            self.add(Shape::image(texture.id(), rect, uv, Color32::WHITE));
+            return;
+        }
+        self.add(Shape::rect_filled(rect, 0.0, Color32::from_gray(220)));
+        let name = self.galley(self.plain(&image.path, Style::sized(8).italic()), width);
+        let name_pos = rect.center() - galley_center(&name);
+        self.add(Shape::galley(name_pos, name));
+    }
+
+    fn tick_boxes(&mut self, labels: &[String], x: f32) {
+        let line_height = self.line_height(FONT_SIZE);
+        let side = line_height * 0.6;
+        let gap = self.width_of(self.plain(" ", Style::default()));
+        let y = self.reserve(line_height);
+
+        let mut x = x;
+        for label in labels {
+            self.rect(x, y + (line_height - side) * 0.6, side, side);
+            x += side + gap;
+            let label = self.galley(self.plain(label, Style::default()), f32::INFINITY);
+            let width = self.size_of(&label).x;
+            self.add(Shape::galley(self.pos(x, y), label));
+            x += width + gap * 4.0;
+        }
+    }
+
+    fn grading_table(&mut self, columns: &[[String; 2]], x: f32, width: f32) {
+        let column = width / columns.len().max(1) as f32;
+        let row_height = self.line_height(10) + 1.0;
+
+        for row in 0..2 {
+            let y = self.reserve(row_height);
+            for (n, cells) in columns.iter().enumerate() {
+                let cell_x = x + n as f32 * column;
+                self.rect(cell_x, y, column, row_height);
+                let galley = self.galley(self.plain(&cells[row], Style::sized(10)), column);
+                let center = self.pos(cell_x + column / 2.0, y + row_height / 2.0);
+                self.add(Shape::galley(center - galley_center(&galley), galley));
+            }
+        }
+    }
+
+    /// A bubble with a letter or digit in it.
+    fn bubble(&mut self, x: f32, y: f32, label: char) {
+        let center = self.pos(x, y);
+        self.add(Shape::circle_stroke(
+            center,
+            BUBBLE_RADIUS as f32 * self.scale,
+            Stroke::new(1.0, Color32::BLACK),
+        ));
+        let galley = self.galley(
+            self.plain(&label.to_string(), Style::sized(7)),
+            f32::INFINITY,
+        );
+        self.add(Shape::galley(center - galley_center(&galley), galley));
+    }
+
+    /// A box to write each digit of the student's ID in, with its bubbles below.
+    fn id_bubbles(&mut self, digits: u8, x: f32) {
+        let spacing = BUBBLE_SPACING as f32;
+        let y = self.reserve(spacing * 11.0);
+        for digit in 0..digits {
+            let center_x = x + spacing * (f32::from(digit) + 0.5);
+            let side = spacing * 0.8;
+            self.rect(center_x - side / 2.0, y, side, side);
+            for n in 0..10 {
+                let label = char::from_digit(n, 10).unwrap_or('?');
+                self.bubble(center_x, y + spacing * (n as f32 + 1.5), label);
+            }
+        }
+    }
+
+    /// A row of answer bubbles for each question. Unlike the PDF, it doesn't fill columns.
+    fn bubble_grid(&mut self, rows: &[(usize, usize)], first_char: char, x: f32) {
+        let spacing = BUBBLE_SPACING as f32;
+        let label_width = spacing * 1.5;
+        for (number, answers) in rows {
+            let y = self.reserve(spacing);
+            let label = self.plain(&format!("{}.", number), Style::sized(10));
+            let galley = self.galley(label, f32::INFINITY);
+            let size = self.size_of(&galley);
+            let label_pos = self.pos(x + label_width - size.x, y + (spacing - size.y) / 2.0);
+            self.add(Shape::galley(label_pos, galley));
+            for pos in 0..*answers {
+                let letter = AlphabeticOrderedList::letter(first_char, pos as u32);
+                let center_x = x + label_width + spacing * (pos as f32 + 0.75);
+                self.bubble(center_x, y + spacing / 2.0, letter);
+            }
+        }
+    }
+}
+
+fn symbol(text: &str) -> MathNode {
+    MathNode::Symbol {
+        text: text.into(),
+        italic: false,
+        spaced: false,
+    }
+}
+
+/// Offset from the top left corner of laid out text to its center.
+fn galley_center(galley: &Galley) -> Vec2 {
+    galley.size() / 2.0
+}
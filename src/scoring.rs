@@ -0,0 +1,393 @@
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::Context;
+
+use crate::{
+    data::{NumericQuestion, Project, Question},
+    paper::{Paper, PaperQuestion},
+    pdf_elements::AlphabeticOrderedList,
+};
+
+/// The answers one student gave on their paper.
+pub struct Response {
+    pub name: String,
+    /// The seed printed on the paper, which regenerates its exact question and answer order.
+    pub paper: u64,
+    /// Question numbers with the letters chosen for them, or the number written for numeric
+    /// questions.
+    pub answers: Vec<(usize, String)>,
+}
+
+pub struct QuestionScore {
+    pub number: usize,
+    /// Index of the question in the project's question bank.
+    pub index: usize,
+    /// Where the chosen answers came from, see `PaperAnswer::source`.
+    pub picks: Vec<usize>,
+    pub points: f64,
+    pub max_points: f64,
+}
+
+pub struct StudentScore {
+    pub name: String,
+    pub paper: u64,
+    pub points: f64,
+    /// Points of the questions that can be scored automatically.
+    pub max_points: f64,
+    /// The grade on the project's grading scale, if it has one.
+    pub grade: Option<String>,
+    pub questions: Vec<QuestionScore>,
+    /// Numbers of the answered questions that need a teacher to score them.
+    pub unscored: Vec<usize>,
+}
+
+/// Reads a CSV of responses. The header names the question number of each column after the
+/// student's name and paper:
+///
+/// ```text
+/// name,paper,1,2,3
+/// Ivan Ivanov,1234567,a,bc,9.81
+/// ```
+pub fn read_responses(path: impl AsRef<Path>) -> anyhow::Result<Vec<Response>> {
+    let path = path.as_ref();
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Could not open {}", path.display()))?;
+
+    let numbers = reader
+        .headers()?
+        .iter()
+        .skip(2)
+        .map(|column| {
+            column
+                .parse::<usize>()
+                .with_context(|| format!("Column '{}' is not a question number", column))
+        })
+        .collect::<anyhow::Result<Vec<usize>>>()?;
+
+    let mut responses = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = i + 2;
+
+        let name = record.get(0).unwrap_or_default().to_string();
+        let paper = record.get(1).unwrap_or_default();
+        let paper = paper
+            .parse()
+            .with_context(|| format!("Line {}: '{}' is not a paper seed", line, paper))?;
+
+        let answers = numbers
+            .iter()
+            .zip(record.iter().skip(2))
+            .map(|(number, letters)| (*number, letters.to_string()))
+            .collect();
+        responses.push(Response {
+            name,
+            paper,
+            answers,
+        });
+    }
+
+    Ok(responses)
+}
+
+/// Turns letters like `ac` or `a, c` into answer positions. Latin letters are accepted for
+/// every language, so responses can be typed on any keyboard.
+fn parse_letters(letters: &str, first_char: char) -> anyhow::Result<BTreeSet<usize>> {
+    letters
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(char::to_lowercase)
+        .map(|c| {
+            let first = if c.is_ascii_lowercase() {
+                'a'
+            } else {
+                first_char
+            };
+            (c as u32)
+                .checked_sub(first as u32)
+                .map(|pos| pos as usize)
+                .with_context(|| format!("'{}' is not an answer letter", c))
+        })
+        .collect()
+}
+
+/// Reads the number written for a numeric question, with or without its unit. A decimal comma is
+/// accepted as well. Returns `None` when the question wasn't answered.
+fn parse_number(text: &str, question: &NumericQuestion) -> anyhow::Result<Option<f64>> {
+    let text = text.trim();
+    let text = text
+        .strip_suffix(question.unit.as_str())
+        .unwrap_or(text)
+        .trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.replace(',', ".")
+        .parse()
+        .map(Some)
+        .with_context(|| format!("'{}' is not a number", text))
+}
+
+/// Scores a selection question with its scoring policy.
+fn score_selection(question: &PaperQuestion, chosen: &BTreeSet<usize>) -> f64 {
+    let Question::Selection(selection) = &question.question else {
+        return 0.0;
+    };
+    let correct = question.answers.iter().filter(|a| a.correct).count();
+    let right = chosen
+        .iter()
+        .filter(|pos| question.answers[**pos].correct)
+        .count();
+
+    selection.scoring.score(
+        selection.points.into(),
+        correct,
+        right,
+        chosen.len() - right,
+    )
+}
+
+pub fn score(project: &Project, response: &Response) -> anyhow::Result<StudentScore> {
+    let paper = Paper::new(project, None, response.paper)?;
+    let first_char = project.settings.language.get_first_char();
+
+    let mut questions = vec![];
+    let mut unscored = vec![];
+    for (number, answer) in response.answers.iter() {
+        let Some(question) = number.checked_sub(1).and_then(|i| paper.questions.get(i)) else {
+            anyhow::bail!("The paper has no question {}", number);
+        };
+        let max_points = question.question.get_points().into();
+        let answer = answer.trim();
+
+        let (picks, points) = match &question.question {
+            Question::Selection(_) => {
+                let chosen = parse_letters(answer, first_char)
+                    .with_context(|| format!("Question {}", number))?;
+                if let Some(pos) = chosen.iter().find(|pos| **pos >= question.answers.len()) {
+                    anyhow::bail!(
+                        "Question {} has no answer {}",
+                        number,
+                        AlphabeticOrderedList::letter(first_char, *pos as u32)
+                    );
+                }
+                let picks = chosen
+                    .iter()
+                    .map(|pos| question.answers[*pos].source)
+                    .collect();
+                (picks, score_selection(question, &chosen))
+            }
+            Question::Numeric(numeric) => {
+                let value = parse_number(answer, numeric)
+                    .with_context(|| format!("Question {}", number))?;
+                let right = value.is_some_and(|value| numeric.accepts(value));
+                (vec![], if right { max_points } else { 0.0 })
+            }
+            // The rest need a teacher, if the student answered them
+            _ => {
+                if !answer.is_empty() {
+                    unscored.push(*number);
+                }
+                continue;
+            }
+        };
+
+        questions.push(QuestionScore {
+            number: *number,
+            index: question.index,
+            picks,
+            points,
+            max_points,
+        });
+    }
+
+    // Summing an empty iterator of floats gives -0
+    let points = questions.iter().fold(0.0, |sum, q| sum + q.points);
+    let max_points = questions.iter().fold(0.0, |sum, q| sum + q.max_points);
+    // Nothing that could be scored says nothing about the student
+    let grade = match max_points > 0.0 {
+        true => project.settings.grading_scale.grade(points, max_points),
+        false => None,
+    };
+    Ok(StudentScore {
+        name: response.name.clone(),
+        paper: response.paper,
+        points,
+        max_points,
+        grade,
+        questions,
+        unscored,
+    })
+}
+
+/// Writes one row per student with their total, grade and the points of every scored question.
+pub fn write_scores(path: impl AsRef<Path>, scores: &[StudentScore]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    let numbers: BTreeSet<usize> = scores
+        .iter()
+        .flat_map(|s| s.questions.iter().map(|q| q.number))
+        .collect();
+    let mut header = vec![
+        "name".to_string(),
+        "paper".into(),
+        "points".into(),
+        "max".into(),
+        "grade".into(),
+    ];
+    header.extend(numbers.iter().map(|n| n.to_string()));
+    writer.write_record(&header)?;
+
+    for score in scores {
+        let mut record = vec![
+            score.name.clone(),
+            score.paper.to_string(),
+            score.points.to_string(),
+            score.max_points.to_string(),
+            score.grade.clone().unwrap_or_default(),
+        ];
+        record.extend(numbers.iter().map(|n| {
+            score
+                .questions
+                .iter()
+                .find(|q| q.number == *n)
+                .map(|q| q.points.to_string())
+                .unwrap_or_default()
+        }));
+        writer.write_record(&record)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{InputQuestion, ScoringPolicy, SelectionQuestion};
+    use crate::settings::{GradingScale, Settings};
+
+    const SEED: u64 = 42;
+
+    fn project() -> Project {
+        Project {
+            questions: vec![
+                Question::Selection(SelectionQuestion {
+                    correct: vec!["4".into(), "2 + 2".into()],
+                    incorrect: vec!["5".into(), "3".into()],
+                    points: 2,
+                    scoring: ScoringPolicy::Proportional,
+                    ..Default::default()
+                }),
+                Question::Numeric(NumericQuestion {
+                    value: 9.81,
+                    unit: "m/s²".into(),
+                    tolerance: 0.05,
+                    points: 3,
+                    ..Default::default()
+                }),
+                Question::Input(InputQuestion::default()),
+            ],
+            ..Default::default()
+        }
+    }
+
+    /// The letters of the answers to the first question that are or aren't correct.
+    fn letters(project: &Project, correct: bool) -> String {
+        let paper = Paper::new(project, None, SEED).unwrap();
+        paper.questions[0]
+            .answers
+            .iter()
+            .enumerate()
+            .filter(|(_, answer)| answer.correct == correct)
+            .map(|(pos, _)| AlphabeticOrderedList::letter('a', pos as u32))
+            .collect()
+    }
+
+    fn response(answers: &[(usize, &str)]) -> Response {
+        Response {
+            name: "Ivan Ivanov".into(),
+            paper: SEED,
+            answers: answers.iter().map(|(n, a)| (*n, a.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_letters() {
+        let set = |positions: &[usize]| positions.iter().copied().collect::<BTreeSet<_>>();
+        assert_eq!(parse_letters("ac", 'a').unwrap(), set(&[0, 2]));
+        assert_eq!(parse_letters("A, c c", 'a').unwrap(), set(&[0, 2]));
+        assert_eq!(parse_letters("", 'a').unwrap(), set(&[]));
+        // Cyrillic letters for Bulgarian papers, Latin ones on any keyboard
+        assert_eq!(parse_letters("аВ", 'а').unwrap(), set(&[0, 2]));
+        assert_eq!(parse_letters("ab", 'а').unwrap(), set(&[0, 1]));
+        assert!(parse_letters("ß", 'а').is_err());
+    }
+
+    #[test]
+    fn scores_selection_and_numeric_questions() {
+        let project = project();
+        let right = letters(&project, true);
+        let result = score(
+            &project,
+            &response(&[(1, &right), (2, "9,8 m/s²"), (3, "x")]),
+        )
+        .unwrap();
+        assert_eq!(result.points, 5.0);
+        assert_eq!(result.max_points, 5.0);
+        assert_eq!(result.unscored, vec![3]);
+        let mut picks = result.questions[0].picks.clone();
+        picks.sort();
+        assert_eq!(picks, vec![0, 1]);
+
+        let half = &right[..1];
+        let result = score(&project, &response(&[(1, half), (2, "9.9")])).unwrap();
+        assert_eq!(result.points, 1.0);
+        assert_eq!(result.max_points, 5.0);
+        assert!(result.unscored.is_empty());
+
+        let result = score(&project, &response(&[(1, ""), (2, "")])).unwrap();
+        assert_eq!(result.points, 0.0);
+        assert_eq!(result.max_points, 5.0);
+    }
+
+    #[test]
+    fn blank_answers_need_no_teacher() {
+        let project = project();
+        let result = score(&project, &response(&[(1, ""), (2, " "), (3, " ")])).unwrap();
+        assert!(result.unscored.is_empty());
+        assert_eq!(result.points, 0.0);
+    }
+
+    #[test]
+    fn no_grade_without_scored_questions() {
+        let project = Project {
+            settings: Settings {
+                grading_scale: GradingScale::bulgarian(),
+                ..Default::default()
+            },
+            ..project()
+        };
+        let result = score(&project, &response(&[(3, "x")])).unwrap();
+        assert_eq!(result.max_points, 0.0);
+        assert_eq!(result.grade, None);
+        assert_eq!(result.unscored, vec![3]);
+
+        let result = score(&project, &response(&[(2, "9.81")])).unwrap();
+        assert_eq!(result.grade.as_deref(), Some("6"));
+    }
+
+    #[test]
+    fn rejects_bad_responses() {
+        let project = project();
+        assert!(score(&project, &response(&[(4, "a")])).is_err());
+        assert!(score(&project, &response(&[(0, "a")])).is_err());
+        assert!(score(&project, &response(&[(1, "e")])).is_err());
+        assert!(score(&project, &response(&[(2, "ten")])).is_err());
+        let wrong = letters(&project, false);
+        assert!(score(&project, &response(&[(1, &wrong)])).is_ok());
+    }
+}
@@ -7,6 +7,22 @@ const fn default_bool_true() -> bool {
     true
 }
 
+const fn default_variants() -> u8 {
+    1
+}
+
+const fn default_student_id_digits() -> u8 {
+    6
+}
+
+fn default_code_font() -> String {
+    "DejaVuSansMono".into()
+}
+
+fn default_math_font() -> String {
+    "DejaVuSerif".into()
+}
+
 #[allow(dead_code)]
 #[derive(Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
 pub enum PaperSize {
@@ -22,11 +38,18 @@ impl Display for PaperSize {
     }
 }
 
-impl Into<Size> for PaperSize {
-    fn into(self) -> Size {
-        let (w, h) = match self {
+impl PaperSize {
+    /// Width and height in millimeters.
+    pub fn dimensions(&self) -> (f32, f32) {
+        match self {
             PaperSize::A4 => (210.0, 297.0),
-        };
+        }
+    }
+}
+
+impl From<PaperSize> for Size {
+    fn from(paper_size: PaperSize) -> Self {
+        let (w, h) = paper_size.dimensions();
 
         Size {
             width: w.into(),
@@ -35,8 +58,133 @@ impl Into<Size> for PaperSize {
     }
 }
 
+/// A grade and the lowest share of the points, in percent, that earns it.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Grade {
+    pub name: String,
+    pub min_percent: f64,
+}
+impl Grade {
+    fn new(name: &str, min_percent: f64) -> Self {
+        Self {
+            name: name.into(),
+            min_percent,
+        }
+    }
+}
+
+/// How the points of a paper are turned into a grade.
+#[derive(Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum GradingScale {
+    #[default]
+    None,
+    /// The grade is the share of the points.
+    Percentage,
+    /// The grade is the first one whose threshold the share of the points reaches.
+    Thresholds { grades: Vec<Grade> },
+}
+impl GradingScale {
+    pub fn bulgarian() -> Self {
+        GradingScale::Thresholds {
+            grades: vec![
+                Grade::new("6", 90.0),
+                Grade::new("5", 75.0),
+                Grade::new("4", 60.0),
+                Grade::new("3", 50.0),
+                Grade::new("2", 0.0),
+            ],
+        }
+    }
+    pub fn letters() -> Self {
+        GradingScale::Thresholds {
+            grades: vec![
+                Grade::new("A", 90.0),
+                Grade::new("B", 80.0),
+                Grade::new("C", 70.0),
+                Grade::new("D", 60.0),
+                Grade::new("F", 0.0),
+            ],
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            GradingScale::None => "None",
+            GradingScale::Percentage => "Percentage",
+            GradingScale::Thresholds { .. } => "Thresholds",
+        }
+    }
+
+    /// The grades from the best one down.
+    fn sorted_grades(grades: &[Grade]) -> Vec<&Grade> {
+        let mut sorted: Vec<&Grade> = grades.iter().collect();
+        sorted.sort_by(|a, b| b.min_percent.total_cmp(&a.min_percent));
+        sorted
+    }
+
+    fn percent(points: f64, max_points: f64) -> f64 {
+        if max_points > 0.0 {
+            points / max_points * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn grade(&self, points: f64, max_points: f64) -> Option<String> {
+        let percent = Self::percent(points, max_points);
+        match self {
+            GradingScale::None => None,
+            GradingScale::Percentage => Some(format!("{:.0}%", percent)),
+            GradingScale::Thresholds { grades } => {
+                let sorted = Self::sorted_grades(grades);
+                let grade = sorted
+                    .iter()
+                    .find(|g| percent >= g.min_percent)
+                    .or(sorted.last())?;
+                Some(grade.name.clone())
+            }
+        }
+    }
+
+    /// Every grade with the range of whole points that earns it, from the best grade down.
+    /// Empty for scales that aren't made of thresholds.
+    pub fn point_ranges(&self, max_points: usize) -> Vec<(String, String)> {
+        let GradingScale::Thresholds { grades } = self else {
+            return vec![];
+        };
+
+        let sorted = Self::sorted_grades(grades);
+        let mut ranges = vec![];
+        // The most points left for the next grade, `None` once every score has a grade
+        let mut upper = Some(max_points);
+        for (i, grade) in sorted.iter().enumerate() {
+            // Found the same way `grade` does, so both agree despite rounding, and the lowest
+            // grade takes everything below the other thresholds
+            let lower = if i + 1 == sorted.len() {
+                Some(0)
+            } else {
+                (0..=max_points).find(|points| {
+                    Self::percent(*points as f64, max_points as f64) >= grade.min_percent
+                })
+            };
+            let range = match (lower, upper) {
+                (Some(lower), Some(upper)) if lower < upper => format!("{}-{}", lower, upper),
+                (Some(lower), Some(upper)) if lower == upper => lower.to_string(),
+                // No whole number of points earns this grade
+                _ => "-".to_string(),
+            };
+            ranges.push((grade.name.clone(), range));
+            if let Some(lower) = lower {
+                upper = upper.min(lower.checked_sub(1));
+            }
+        }
+        ranges
+    }
+}
+
 #[allow(dead_code)]
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Settings {
     #[serde(default = "default_bool_true")]
     pub show_hints: bool,
@@ -45,9 +193,32 @@ pub struct Settings {
     pub language: Language,
     pub fonts_path: String,
     pub font: String,
+    /// Monospace font family for code blocks, loaded from `fonts_path` like `font`.
+    #[serde(default = "default_code_font")]
+    pub code_font: String,
+    #[serde(default)]
+    pub code_line_numbers: bool,
+    #[serde(default = "default_bool_true")]
+    pub code_frame: bool,
+    /// Font family for formulas, it needs Greek letters and math symbols.
+    #[serde(default = "default_math_font")]
+    pub math_font: String,
     pub output: String,
     pub max_questions: u8,
     pub randomize_questions: bool,
+    #[serde(default = "default_variants")]
+    pub variants: u8,
+    /// Drives every shuffle, so a paper can be regenerated identically. A random seed is picked
+    /// for each generation when this is not set.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Appends a machine-readable bubble sheet to every paper.
+    #[serde(default)]
+    pub answer_sheet: bool,
+    #[serde(default = "default_student_id_digits")]
+    pub student_id_digits: u8,
+    #[serde(default)]
+    pub grading_scale: GradingScale,
 }
 impl Default for Settings {
     fn default() -> Self {
@@ -57,24 +228,29 @@ impl Default for Settings {
             language: crate::settings::Language::English,
             fonts_path: "./assets/fonts".into(),
             font: "TimesNewRoman".into(),
+            code_font: default_code_font(),
+            code_line_numbers: false,
+            code_frame: true,
+            math_font: default_math_font(),
             output: "output.pdf".into(),
             max_questions: 20,
             randomize_questions: false,
+            variants: 1,
+            seed: None,
+            answer_sheet: false,
+            student_id_digits: default_student_id_digits(),
+            grading_scale: GradingScale::None,
         }
     }
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[derive(Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub enum Language {
+    #[default]
     English,
     Bulgarian,
 }
-impl Default for Language {
-    fn default() -> Self {
-        Self::English
-    }
-}
 impl Language {
     #[inline]
     pub fn get_first_char(&self) -> char {
@@ -91,7 +267,7 @@ impl Language {
         }
     }
     #[inline]
-    pub fn format_points(&self, points: u8) -> String {
+    pub fn format_points(&self, points: usize) -> String {
         match self {
             Language::English => format!("__/{points}pt"),
             Language::Bulgarian => format!("__/{points}т"),
@@ -140,4 +316,220 @@ impl Language {
             Language::Bulgarian => "Точки",
         }
     }
+    #[inline]
+    pub fn group(&self) -> &str {
+        match self {
+            Language::English => "Group",
+            Language::Bulgarian => "Група",
+        }
+    }
+    #[inline]
+    pub fn seed(&self) -> &str {
+        match self {
+            Language::English => "Seed",
+            Language::Bulgarian => "Код",
+        }
+    }
+    #[inline]
+    pub fn random_seed(&self) -> &str {
+        match self {
+            Language::English => "random",
+            Language::Bulgarian => "случаен",
+        }
+    }
+    #[inline]
+    pub fn true_name(&self) -> &str {
+        match self {
+            Language::English => "True",
+            Language::Bulgarian => "Вярно",
+        }
+    }
+    #[inline]
+    pub fn false_name(&self) -> &str {
+        match self {
+            Language::English => "False",
+            Language::Bulgarian => "Грешно",
+        }
+    }
+    #[inline]
+    pub fn answer(&self) -> &str {
+        match self {
+            Language::English => "Answer",
+            Language::Bulgarian => "Отговор",
+        }
+    }
+    #[inline]
+    pub fn answer_key(&self) -> &str {
+        match self {
+            Language::English => "Answer key",
+            Language::Bulgarian => "Ключ с отговори",
+        }
+    }
+    #[inline]
+    pub fn correct_answers(&self) -> &str {
+        match self {
+            Language::English => "Correct",
+            Language::Bulgarian => "Верни",
+        }
+    }
+    #[inline]
+    pub fn scoring(&self) -> &str {
+        match self {
+            Language::English => "Scoring",
+            Language::Bulgarian => "Оценяване",
+        }
+    }
+    #[inline]
+    pub fn all_or_nothing(&self) -> &str {
+        match self {
+            Language::English => "all or nothing",
+            Language::Bulgarian => "всичко или нищо",
+        }
+    }
+    #[inline]
+    pub fn per_correct_answer(&self) -> &str {
+        match self {
+            Language::English => "per correct answer",
+            Language::Bulgarian => "за верен отговор",
+        }
+    }
+    #[inline]
+    pub fn per_wrong_answer(&self) -> &str {
+        match self {
+            Language::English => "per wrong answer",
+            Language::Bulgarian => "за грешен отговор",
+        }
+    }
+    #[inline]
+    pub fn at_least_zero(&self) -> &str {
+        match self {
+            Language::English => "at least 0",
+            Language::Bulgarian => "най-малко 0",
+        }
+    }
+    #[inline]
+    pub fn open_answer(&self) -> &str {
+        match self {
+            Language::English => "Open answer",
+            Language::Bulgarian => "Свободен отговор",
+        }
+    }
+    #[inline]
+    pub fn answer_sheet(&self) -> &str {
+        match self {
+            Language::English => "Answer sheet",
+            Language::Bulgarian => "Лист за отговори",
+        }
+    }
+    #[inline]
+    pub fn student_id(&self) -> &str {
+        match self {
+            Language::English => "Student ID",
+            Language::Bulgarian => "Ученически номер",
+        }
+    }
+    #[inline]
+    pub fn answer_sheet_instructions(&self) -> &str {
+        match self {
+            Language::English => "Fill in the circles of your answers completely with a dark pen.",
+            Language::Bulgarian => "Запълнете изцяло кръговете на отговорите си с тъмна химикалка.",
+        }
+    }
+    #[inline]
+    pub fn grade(&self) -> &str {
+        match self {
+            Language::English => "Grade",
+            Language::Bulgarian => "Оценка",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The point ranges as `grade: range`.
+    fn ranges(scale: &GradingScale, max_points: usize) -> Vec<String> {
+        scale
+            .point_ranges(max_points)
+            .into_iter()
+            .map(|(grade, range)| format!("{}: {}", grade, range))
+            .collect()
+    }
+
+    #[test]
+    fn grade_boundaries() {
+        let scale = GradingScale::bulgarian();
+        let grade = |points| scale.grade(points, 20.0).unwrap();
+        assert_eq!(grade(20.0), "6");
+        assert_eq!(grade(18.0), "6");
+        assert_eq!(grade(17.9), "5");
+        assert_eq!(grade(15.0), "5");
+        assert_eq!(grade(12.0), "4");
+        assert_eq!(grade(10.0), "3");
+        assert_eq!(grade(9.5), "2");
+        assert_eq!(grade(0.0), "2");
+        // Penalties can take a score below zero
+        assert_eq!(grade(-2.0), "2");
+
+        assert_eq!(GradingScale::letters().grade(7.0, 10.0).unwrap(), "C");
+
+        let percentage = GradingScale::Percentage;
+        assert_eq!(percentage.grade(2.0, 3.0).unwrap(), "67%");
+        assert_eq!(GradingScale::None.grade(2.0, 3.0), None);
+    }
+
+    #[test]
+    fn grade_without_points() {
+        assert_eq!(GradingScale::bulgarian().grade(0.0, 0.0).unwrap(), "2");
+        assert_eq!(GradingScale::Percentage.grade(0.0, 0.0).unwrap(), "0%");
+        assert_eq!(
+            ranges(&GradingScale::bulgarian(), 0),
+            vec!["6: -", "5: -", "4: -", "3: -", "2: 0"]
+        );
+    }
+
+    #[test]
+    fn point_ranges() {
+        assert_eq!(
+            ranges(&GradingScale::bulgarian(), 20),
+            vec!["6: 18-20", "5: 15-17", "4: 12-14", "3: 10-11", "2: 0-9",]
+        );
+        // 70% of 10 points comes out a little over 7 in floating point
+        assert_eq!(
+            ranges(&GradingScale::letters(), 10),
+            vec!["A: 9-10", "B: 8", "C: 7", "D: 6", "F: 0-5"]
+        );
+        assert!(GradingScale::Percentage.point_ranges(10).is_empty());
+    }
+
+    #[test]
+    fn point_ranges_collapse() {
+        assert_eq!(
+            ranges(&GradingScale::bulgarian(), 1),
+            vec!["6: 1", "5: -", "4: -", "3: -", "2: 0"]
+        );
+        assert_eq!(
+            ranges(&GradingScale::bulgarian(), 4),
+            vec!["6: 4", "5: 3", "4: -", "3: 2", "2: 0-1"]
+        );
+
+        // Unsorted thresholds, two of them at zero and the lowest one above zero
+        let scale = GradingScale::Thresholds {
+            grades: vec![
+                Grade::new("fail", 0.0),
+                Grade::new("pass", 50.0),
+                Grade::new("also fail", 0.0),
+            ],
+        };
+        assert_eq!(
+            ranges(&scale, 4),
+            vec!["pass: 2-4", "fail: 0-1", "also fail: -"]
+        );
+        let scale = GradingScale::Thresholds {
+            grades: vec![Grade::new("pass", 50.0), Grade::new("fail", 20.0)],
+        };
+        assert_eq!(scale.grade(0.0, 4.0).unwrap(), "fail");
+        assert_eq!(ranges(&scale, 4), vec!["pass: 2-4", "fail: 0-1"]);
+    }
 }